@@ -0,0 +1,58 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hvalfangst_rust_crud_with_axum::users::{
+    model::UpsertUser,
+    service::{UserStore, create_user, get_user_by_email},
+};
+
+const USERS: usize = 1_000;
+const GETS_PER_TASK: usize = 100;
+
+async fn populated_store() -> UserStore {
+    let store = UserStore::new();
+    for i in 0..USERS {
+        let request = UpsertUser {
+            email: format!("user{}@vandelay.com", i),
+            password: "importer_exporter".to_string(),
+            fullname: "Art Vandelay".to_string(),
+            role: "architect".to_string(),
+        };
+        create_user(request, &store).await.unwrap();
+    }
+    store
+}
+
+// Spawns `tasks` concurrent readers, each issuing GETS_PER_TASK lookups against the shared store
+async fn parallel_gets(store: UserStore, tasks: usize) {
+    let handles: Vec<_> = (0..tasks)
+        .map(|task| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                for i in 0..GETS_PER_TASK {
+                    let email = format!("user{}@vandelay.com", (task * GETS_PER_TASK + i) % USERS);
+                    assert!(get_user_by_email(&email, &store).await.unwrap().is_some());
+                }
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+fn bench_parallel_gets(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let store = runtime.block_on(populated_store());
+
+    let mut group = c.benchmark_group("parallel_gets");
+    for tasks in [1, 4, 16, 64] {
+        group.throughput(Throughput::Elements((tasks * GETS_PER_TASK) as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(tasks), &tasks, |b, &tasks| {
+            b.to_async(&runtime).iter(|| parallel_gets(store.clone(), tasks));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parallel_gets);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+CREATE TABLE users (
+    id INTEGER GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    email TEXT NOT NULL,
+    password TEXT NOT NULL,
+    fullname TEXT NOT NULL,
+    role TEXT NOT NULL
+);
+
+-- Emails that differ only in case or surrounding whitespace belong to the same user
+CREATE UNIQUE INDEX users_normalized_email_key ON users (lower(btrim(email)));
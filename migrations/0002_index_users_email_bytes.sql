@@ -0,0 +1,2 @@
+-- Export pages through users in byte order of their email, see PostgresRepository::list_page
+CREATE INDEX users_email_bytes_idx ON users (email COLLATE "C");
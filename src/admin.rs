@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+    Json
+};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use crate::{
+    config::{Secret, StorageConfig},
+    request_id::{RequestId, REQUEST_ID_HEADER},
+    storage::snapshot::{write_snapshot, SnapshotInfo},
+    telemetry::trace_layer,
+    users::{model::ErrorBody, service::UserStore}
+};
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub storage: StorageConfig,
+    pub store: UserStore,
+}
+
+// - - - - - - - - - - - [ROUTES] - - - - - - - - - - -
+
+// Operator endpoints, only reachable with `Authorization: Bearer <auth.admin_token>`
+pub fn admin_routes(admin_token: Secret, state: AdminState) -> Router {
+    Router::new()
+        .route("/admin/snapshot", post(snapshot_handler))
+        .route_layer(middleware::from_fn_with_state(Arc::new(admin_token), require_admin_token))
+        .layer(trace_layer())
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+        .with_state(state)
+}
+
+fn error_response(status: StatusCode, request_id: &RequestId, message: &str) -> Response {
+    (status, Json(ErrorBody { error: message.to_string(), request_id: request_id.0.clone(), field: None })).into_response()
+}
+
+async fn require_admin_token<B>(
+    State(admin_token): State<Arc<Secret>>,
+    request: Request<B>,
+    next: Next<B>
+) -> Response {
+    if bearer_token(request.headers()).is_some_and(|token| admin_token.matches(token)) {
+        return next.run(request).await;
+    }
+
+    let request_id = RequestId::from_headers(request.headers()).unwrap_or_else(|| RequestId("unknown".to_string()));
+    let mut response = error_response(StatusCode::UNAUTHORIZED, &request_id, "A valid admin token is required");
+    response.headers_mut().insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+    response
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+// - - - - - - - - - - - [HANDLERS] - - - - - - - - - - -
+
+pub async fn snapshot_handler(
+    State(state): State<AdminState>,
+    request_id: RequestId
+) -> Result<Json<SnapshotInfo>, Response> {
+    let Some(path) = &state.storage.snapshot.path else {
+        return Err(error_response(StatusCode::CONFLICT, &request_id, "storage.snapshot.path is not configured"));
+    };
+
+    write_snapshot(path, &state.store).await.map(Json).map_err(|error| {
+        tracing::error!(%error, "snapshot failed");
+        error_response(StatusCode::INTERNAL_SERVER_ERROR, &request_id, "Snapshot failed")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(header::AUTHORIZATION, "Basic c2VyZW5pdHk6bm93".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(header::AUTHORIZATION, "bearer  serenity_now".parse().unwrap());
+        assert_eq!(bearer_token(&headers), Some("serenity_now"));
+    }
+}
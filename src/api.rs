@@ -0,0 +1,107 @@
+use std::time::UNIX_EPOCH;
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware,
+    response::Response,
+    Router
+};
+use crate::config::{ApiConfig, VersionPolicy};
+
+// Every version this binary can mount. A breaking change gets a new entry here and its own router,
+// mounted next to the previous one until that version is sunset
+pub const VERSIONS: &[&str] = &["v1"];
+
+// RFC 9745 and RFC 8594
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+// - - - - - - - - - - - [ROUTES] - - - - - - - - - - -
+
+// Nests each router under /<version> and serves the configured root alias at the root paths as well
+pub fn api_routes(config: &ApiConfig, versions: impl IntoIterator<Item = (&'static str, Router)>) -> Router {
+    let mut app = Router::new();
+
+    for (name, routes) in versions {
+        let routes = with_lifecycle_headers(routes, lifecycle_headers(config.versions.get(name)));
+
+        if config.root_alias_enabled && config.root_alias == name {
+            app = app.merge(routes.clone());
+        }
+        app = app.nest(&format!("/{}", name), routes);
+    }
+
+    app
+}
+
+// Strips a leading /<version> from a route template, so /v1/users and the root alias /users are the same route
+pub fn unversioned_path(path: &str) -> &str {
+    VERSIONS.iter()
+        .filter_map(|version| path.strip_prefix('/').and_then(|rest| rest.strip_prefix(version)))
+        .find(|rest| rest.starts_with('/'))
+        .unwrap_or(path)
+}
+
+fn with_lifecycle_headers(routes: Router, headers: HeaderMap) -> Router {
+    if headers.is_empty() {
+        return routes;
+    }
+
+    routes.layer(middleware::map_response(move |mut response: Response| {
+        let headers = headers.clone();
+        async move {
+            response.headers_mut().extend(headers);
+            response
+        }
+    }))
+}
+
+// - - - - - - - - - - - [HEADERS] - - - - - - - - - - -
+
+// Config validation guarantees the dates parse, anything else is skipped rather than sent malformed
+pub fn lifecycle_headers(policy: Option<&VersionPolicy>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let Some(policy) = policy else { return headers };
+
+    let deprecated_at = policy.deprecated_at.as_deref().and_then(|date| httpdate::parse_http_date(date).ok());
+    if let Some(seconds) = deprecated_at.and_then(|date| date.duration_since(UNIX_EPOCH).ok()).map(|since| since.as_secs()) {
+        headers.insert(DEPRECATION, HeaderValue::from_str(&format!("@{}", seconds)).unwrap());
+    }
+
+    let sunset_at = policy.sunset_at.as_deref().and_then(|date| httpdate::parse_http_date(date).ok());
+    if let Some(date) = sunset_at {
+        headers.insert(SUNSET, HeaderValue::from_str(&httpdate::fmt_http_date(date)).unwrap());
+    }
+
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_headers() {
+        let policy = VersionPolicy {
+            deprecated_at: Some("Sun, 01 Jun 2025 00:00:00 GMT".to_string()),
+            sunset_at: Some("Mon, 01 Dec 2025 00:00:00 GMT".to_string()),
+        };
+
+        let headers = lifecycle_headers(Some(&policy));
+
+        assert_eq!(headers["deprecation"], "@1748736000");
+        assert_eq!(headers["sunset"], "Mon, 01 Dec 2025 00:00:00 GMT");
+    }
+
+    #[test]
+    fn test_unversioned_path() {
+        assert_eq!(unversioned_path("/v1/users/:email"), "/users/:email");
+        assert_eq!(unversioned_path("/users"), "/users");
+        assert_eq!(unversioned_path("/v1users"), "/v1users");
+    }
+
+    #[test]
+    fn test_no_policy_sends_no_headers() {
+        assert!(lifecycle_headers(None).is_empty());
+        assert!(lifecycle_headers(Some(&VersionPolicy::default())).is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    fs,
+    io::{self, Read},
+    net::SocketAddr,
+    path::{Path, PathBuf}
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crate::{
+    config::{Config, ConfigError},
+    server,
+    storage::{self, RepositoryError, StorageError},
+    telemetry::{self, TelemetryError},
+    users::{
+        import::{import_csv, ColumnMapping},
+        model::{ImportAction, UpsertUser, User, validate_email},
+        service::{UserStore, create_user, get_all_users}
+    }
+};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+// - - - - - - - - - - - [ARGUMENTS] - - - - - - - - - - -
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to a TOML configuration file
+    #[arg(long, global = true, env = "APP_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration with secrets redacted and exit
+    #[arg(long, global = true)]
+    pub print_config: bool,
+
+    /// Defaults to `serve` when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server
+    Serve(ServeArgs),
+    /// Apply pending storage migrations
+    Migrate,
+    /// Import users from a JSON array of {email, password, fullname, role} objects, or upsert them by email from a CSV file
+    Import {
+        /// File to read, or '-' for stdin
+        file: PathBuf,
+        /// Format of the file, CSV for a .csv extension and JSON otherwise when omitted
+        #[arg(long, value_enum)]
+        format: Option<ImportFormat>,
+        /// Map a CSV column to a user field, e.g. --map 'E-mail Address=email'; may be repeated
+        #[arg(long = "map", value_name = "HEADER=FIELD")]
+        map: Vec<String>,
+        /// Report what a CSV import would do without changing any user
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export all users as a JSON array
+    Export {
+        /// File to write instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Create the first admin account directly in storage
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        fullname: String,
+        #[arg(long, env = "APP_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ServeArgs {
+    /// Address to listen on, overriding server.bind_address and server.port
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+}
+
+// - - - - - - - - - - - [ERRORS] - - - - - - - - - - -
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed user data: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Telemetry(#[from] TelemetryError),
+    #[error("server error: {0}")]
+    Server(#[from] hyper::Error),
+    #[error("{0}")]
+    Rejected(String),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Config(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+// - - - - - - - - - - - [COMMANDS] - - - - - - - - - - -
+
+pub async fn run(cli: Cli) -> Result<(), CliError> {
+    let command = cli.command.unwrap_or(Command::Serve(ServeArgs::default()));
+    let mut config = Config::load(cli.config.as_deref())?;
+
+    if let Command::Serve(ServeArgs { bind: Some(bind) }) = &command {
+        config.server.bind_address = bind.ip();
+        config.server.port = bind.port();
+        config.validate()?;
+    }
+
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml());
+        return Ok(());
+    }
+
+    let telemetry = telemetry::init(&config.log, &config.otel)?;
+
+    let result = run_command(command, config).await;
+    telemetry.shutdown().await;
+    result
+}
+
+async fn run_command(command: Command, config: Config) -> Result<(), CliError> {
+    match command {
+        Command::Serve(_) => server::serve(config).await,
+        Command::Migrate => {
+            let applied = storage::migrate(&config.storage).await?;
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for migration in applied {
+                println!("Applied migration {}", migration);
+            }
+            Ok(())
+        }
+        Command::Import { file, format, map, dry_run } => {
+            let format = format.unwrap_or(match file.extension() {
+                Some(extension) if extension.eq_ignore_ascii_case("csv") => ImportFormat::Csv,
+                _ => ImportFormat::Json,
+            });
+            if format == ImportFormat::Csv {
+                return import_csv_file(&file, &map, dry_run, &config).await;
+            }
+            if !map.is_empty() || dry_run {
+                return Err(CliError::Rejected("--map and --dry-run only apply to CSV imports".to_string()));
+            }
+
+            storage::ensure_persistent(&config.storage)?;
+            let requests: Vec<UpsertUser> = serde_json::from_str(&read_input(&file)?)?;
+            let store = storage::open(&config.storage).await?;
+
+            let summary = import_users(requests, &store).await?;
+            storage::flush(&config.storage, &store).await?;
+            for problem in &summary.rejected {
+                eprintln!("Skipped: {}", problem);
+            }
+            println!("Imported {} user(s), skipped {}", summary.imported, summary.rejected.len());
+            Ok(())
+        }
+        Command::Export { output } => {
+            storage::ensure_persistent(&config.storage)?;
+            let store = storage::open(&config.storage).await?;
+
+            let users = get_all_users(&store).await?;
+            let json = serde_json::to_string_pretty(&users)?;
+            match output {
+                Some(path) => fs::write(path, json)?,
+                None => println!("{}", json),
+            }
+            Ok(())
+        }
+        Command::CreateAdmin { email, fullname, password } => {
+            storage::ensure_persistent(&config.storage)?;
+            let store = storage::open(&config.storage).await?;
+
+            let admin = create_admin(UpsertUser { email, password, fullname, role: ADMIN_ROLE.to_string() }, &store).await?;
+            storage::flush(&config.storage, &store).await?;
+            println!("Created admin '{}' with id {}", admin.email, admin.id);
+            Ok(())
+        }
+    }
+}
+
+async fn import_csv_file(file: &Path, map: &[String], dry_run: bool, config: &Config) -> Result<(), CliError> {
+    let mapping = ColumnMapping::from_pairs(map.iter().map(String::as_str)).map_err(CliError::Rejected)?;
+    storage::ensure_persistent(&config.storage)?;
+    let csv = read_input(file)?;
+    let store = storage::open(&config.storage).await?;
+
+    let report = import_csv(csv.as_bytes(), &mapping, dry_run, &store).await.map_err(CliError::Rejected)?;
+    if !dry_run {
+        storage::flush(&config.storage, &store).await?;
+    }
+    for row in report.rows.iter().filter(|row| row.action == ImportAction::Rejected) {
+        eprintln!("Rejected line {}: {}", row.row, row.reason.as_deref().unwrap_or_default());
+    }
+
+    let prefix = if dry_run { "Dry run: would have created" } else { "Created" };
+    println!("{} {} user(s), updated {}, rejected {}", prefix, report.created, report.updated, report.rejected);
+    Ok(())
+}
+
+fn read_input(path: &Path) -> io::Result<String> {
+    if path == Path::new("-") {
+        let mut buffer = String::new();
+        io::stdin().read_to_string(&mut buffer)?;
+        Ok(buffer)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub rejected: Vec<String>,
+}
+
+pub async fn import_users(requests: Vec<UpsertUser>, store: &UserStore) -> Result<ImportSummary, RepositoryError> {
+    let mut summary = ImportSummary::default();
+
+    for request in requests {
+        if !validate_email(&request) {
+            summary.rejected.push(format!("invalid email '{}'", request.email));
+            continue;
+        }
+
+        let email = request.email.clone();
+        match create_user(request, store).await {
+            Ok(_) => summary.imported += 1,
+            Err(RepositoryError::Duplicate) => summary.rejected.push(format!("user '{}' already exists", email)),
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(summary)
+}
+
+pub async fn create_admin(request: UpsertUser, store: &UserStore) -> Result<User, CliError> {
+    if !validate_email(&request) {
+        return Err(CliError::Rejected(format!("invalid email '{}'", request.email)));
+    }
+
+    if get_all_users(store).await?.iter().any(|user| user.role == ADMIN_ROLE) {
+        return Err(CliError::Rejected("an admin account already exists".to_string()));
+    }
+
+    let email = request.email.clone();
+    create_user(request, store).await.map_err(|error| match error {
+        RepositoryError::Duplicate => CliError::Rejected(format!("user '{}' already exists", email)),
+        error => CliError::Repository(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_hashmap() -> UserStore {
+        UserStore::new()
+    }
+
+    fn create_test_upsert_user(email: &str, role: &str) -> UpsertUser {
+        UpsertUser {
+            email: email.to_string(),
+            password: "hoochie_mama".to_string(),
+            fullname: "Jerry Seinfeld".to_string(),
+            role: role.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_serve_is_default_command() {
+        let cli = Cli::try_parse_from(["app"]).unwrap();
+
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_parse_serve_with_bind() {
+        let cli = Cli::try_parse_from(["app", "serve", "--bind", "127.0.0.1:3000", "--config", "app.toml"]).unwrap();
+
+        match cli.command {
+            Some(Command::Serve(args)) => assert_eq!(args.bind, Some("127.0.0.1:3000".parse().unwrap())),
+            other => panic!("Expected serve, got {:?}", other),
+        }
+        assert_eq!(cli.config, Some(PathBuf::from("app.toml")));
+    }
+
+    #[test]
+    fn test_parse_create_admin() {
+        let cli = Cli::try_parse_from([
+            "app", "create-admin", "--email", "george@vandelay.com", "--fullname", "George Costanza", "--password", "bosco",
+        ]).unwrap();
+
+        assert!(matches!(cli.command, Some(Command::CreateAdmin { .. })));
+    }
+
+    #[test]
+    fn test_parse_csv_import() {
+        let cli = Cli::try_parse_from([
+            "app", "import", "customers.txt", "--format", "csv", "--map", "Contact=email", "--map", "Secret=password", "--dry-run",
+        ]).unwrap();
+
+        match cli.command {
+            Some(Command::Import { format, map, dry_run, .. }) => {
+                assert_eq!(format, Some(ImportFormat::Csv));
+                assert_eq!(map, ["Contact=email", "Secret=password"]);
+                assert!(dry_run);
+            }
+            other => panic!("Expected import, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_users_reports_rejections() {
+        let hashmap = create_test_hashmap();
+        let requests = vec![
+            create_test_upsert_user("jerry@seinfeld.com", "comedian"),
+            create_test_upsert_user("jerry@seinfeld.com", "comedian"),
+            create_test_upsert_user("not-an-email", "comedian"),
+        ];
+
+        let summary = import_users(requests, &hashmap).await.unwrap();
+
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.rejected.len(), 2);
+        assert_eq!(get_all_users(&hashmap).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_admin_only_once() {
+        let hashmap = create_test_hashmap();
+
+        let admin = create_admin(create_test_upsert_user("george@vandelay.com", ADMIN_ROLE), &hashmap).await.unwrap();
+        assert_eq!(admin.role, ADMIN_ROLE);
+
+        let second = create_admin(create_test_upsert_user("elaine@vandelay.com", ADMIN_ROLE), &hashmap).await;
+        assert!(matches!(second, Err(CliError::Rejected(_))));
+    }
+}
@@ -0,0 +1,52 @@
+use axum::{
+    body::{Body, HttpBody},
+    error_handling::HandleErrorLayer,
+    http::StatusCode,
+    BoxError,
+    Router
+};
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer
+    },
+    decompression::{DecompressionBody, RequestDecompressionLayer},
+    map_request_body::MapRequestBodyLayer
+};
+use crate::config::{CompressionAlgorithm, CompressionConfig};
+
+// Compresses responses with the best algorithm the client lists in Accept-Encoding and decompresses request
+// bodies sent with Content-Encoding. DefaultBodyLimit applies to the decompressed body, so a small compressed
+// upload cannot expand past limits.max_body_bytes
+pub fn compressed(routes: Router, config: &CompressionConfig) -> Router {
+    if !config.enabled {
+        return routes;
+    }
+
+    let enabled = |algorithm| config.algorithms.contains(&algorithm);
+    let predicate = SizeAbove::new(config.min_size_bytes)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES);
+
+    routes
+        .layer(MapRequestBodyLayer::new(into_body))
+        .layer(ServiceBuilder::new()
+            // The decompression service widens the error type of the routes, which cannot fail, to BoxError
+            .layer(HandleErrorLayer::new(|_: BoxError| async { StatusCode::INTERNAL_SERVER_ERROR }))
+            .layer(RequestDecompressionLayer::new()
+                .gzip(enabled(CompressionAlgorithm::Gzip))
+                .br(enabled(CompressionAlgorithm::Br))
+                .zstd(enabled(CompressionAlgorithm::Zstd))))
+        .layer(CompressionLayer::new()
+            .gzip(enabled(CompressionAlgorithm::Gzip))
+            .br(enabled(CompressionAlgorithm::Br))
+            .zstd(enabled(CompressionAlgorithm::Zstd))
+            .compress_when(predicate))
+}
+
+// Handlers and extractors are written against the plain request body
+fn into_body(body: DecompressionBody<Body>) -> Body {
+    let mut body = Box::pin(body);
+    Body::wrap_stream(futures::stream::poll_fn(move |cx| body.as_mut().poll_data(cx)))
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+use http::{HeaderName, HeaderValue, Method};
+use regex::Regex;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use crate::{
+    config::CorsConfig,
+    request_id::REQUEST_ID_HEADER
+};
+
+// Headers browser clients may read from responses: the request id for support tickets and the
+// lifecycle headers announcing a deprecated API version
+const EXPOSED_HEADERS: [HeaderName; 3] = [
+    REQUEST_ID_HEADER,
+    HeaderName::from_static("deprecation"),
+    HeaderName::from_static("sunset"),
+];
+
+// Answers preflight requests itself and adds the CORS headers to responses for allowed origins.
+// Requests from other origins are passed through without those headers, so the browser blocks them.
+// Config validation guarantees every value below parses, anything else is skipped
+pub fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let methods: Vec<Method> = config.allowed_methods.iter()
+        .filter_map(|method| method.parse().ok())
+        .collect();
+    let headers: Vec<HeaderName> = config.allowed_headers.iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(allow_origin(config))
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.allow_credentials)
+        .expose_headers(EXPOSED_HEADERS)
+        .max_age(Duration::from_secs(config.max_age_secs))
+}
+
+fn allow_origin(config: &CorsConfig) -> AllowOrigin {
+    if config.allowed_origins.iter().any(|origin| origin == "*") {
+        return AllowOrigin::any();
+    }
+
+    let origins: Vec<HeaderValue> = config.allowed_origins.iter()
+        .filter_map(|origin| origin.parse().ok())
+        .collect();
+
+    // Anchored so that e.g. https://admin.example.com.evil.net cannot match a pattern meant for example.com
+    let pattern = config.allowed_origin_regex.as_ref()
+        .and_then(|pattern| Regex::new(&format!("^(?:{})$", pattern)).ok());
+
+    match pattern {
+        None => AllowOrigin::list(origins),
+        Some(pattern) => AllowOrigin::predicate(move |origin, _| {
+            origins.contains(origin) || origin.to_str().is_ok_and(|origin| pattern.is_match(origin))
+        }),
+    }
+}
@@ -0,0 +1,105 @@
+use async_trait::async_trait;
+use axum::{
+    body::{Bytes, HttpBody},
+    extract::FromRequest,
+    http::{Request, StatusCode},
+    BoxError,
+    Json
+};
+use serde::de::DeserializeOwned;
+use serde_json::error::Category;
+use crate::{
+    format::Format,
+    request_id::RequestId,
+    users::model::ErrorBody
+};
+
+// Replacement for axum's Json extractor that also reads CBOR and MessagePack bodies, chosen by Content-Type.
+// Rejections use the same JSON error body as the handlers, including the path of the offending field such as
+// "role" or "address.city"
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiBody<T>(pub T);
+
+pub type JsonRejection = (StatusCode, Json<ErrorBody>);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for ApiBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = JsonRejection;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let request_id = RequestId::from_headers(request.headers()).unwrap_or_else(|| RequestId("unknown".to_string()));
+        let reject = |status: StatusCode, error: String, field: Option<String>| {
+            (status, Json(ErrorBody { error, request_id: request_id.0.clone(), field }))
+        };
+
+        let Some(format) = Format::from_content_type(request.headers()) else {
+            let supported: Vec<&str> = Format::ALL.iter().map(|format| format.content_type()).collect();
+            let error = format!("Expected a request with a Content-Type of {}", supported.join(", "));
+            return Err(reject(StatusCode::UNSUPPORTED_MEDIA_TYPE, error, None));
+        };
+
+        // Reading the body is where DefaultBodyLimit is enforced
+        let bytes = Bytes::from_request(request, state).await.map_err(|rejection| match rejection.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => reject(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large".to_string(), None),
+            status => reject(status, rejection.body_text(), None),
+        })?;
+
+        if format != Format::Json {
+            let document = format.decode_document(&bytes)
+                .map_err(|error| reject(StatusCode::BAD_REQUEST, format!("Invalid request body: {}", error), None))?;
+            let value = serde_path_to_error::deserialize(document).map_err(|error| {
+                let field = field_path(&error);
+                reject_invalid(reject, error.into_inner(), field)
+            })?;
+            return Ok(ApiBody(value));
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|error| {
+            let field = field_path(&error);
+            reject_invalid(reject, error.into_inner(), field)
+        })?;
+
+        // Rejects trailing data after the JSON value, as axum's extractor does
+        deserializer.end().map_err(|error| reject_invalid(reject, error, None))?;
+
+        Ok(ApiBody(value))
+    }
+}
+
+fn reject_invalid(
+    reject: impl FnOnce(StatusCode, String, Option<String>) -> JsonRejection,
+    error: serde_json::Error,
+    field: Option<String>
+) -> JsonRejection {
+    let status = match error.classify() {
+        Category::Data => StatusCode::UNPROCESSABLE_ENTITY,
+        Category::Syntax | Category::Eof | Category::Io => StatusCode::BAD_REQUEST,
+    };
+    reject(status, format!("Invalid request body: {}", error), field)
+}
+
+// serde reports a missing field at the path of the object that lacks it, so the field name from the
+// message is appended to point at the field itself
+pub(crate) fn field_path(error: &serde_path_to_error::Error<serde_json::Error>) -> Option<String> {
+    let path = error.path().to_string();
+    let parent = if path == "." { None } else { Some(path) };
+
+    let message = error.inner().to_string();
+    let missing = message.strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(field, _)| field.to_string());
+
+    match (parent, missing) {
+        (Some(parent), Some(field)) => Some(format!("{}.{}", parent, field)),
+        (None, Some(field)) => Some(field),
+        (parent, None) => parent,
+    }
+}
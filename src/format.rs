@@ -0,0 +1,230 @@
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json
+};
+use crate::{
+    request_id::RequestId,
+    users::model::ErrorBody
+};
+
+// Encodings the user endpoints speak. JSON is the default and the fallback; error bodies are always JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Cbor,
+    MsgPack,
+}
+
+impl Format {
+    // In order of preference when a client accepts several equally
+    pub const ALL: [Format; 3] = [Format::Json, Format::Cbor, Format::MsgPack];
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Cbor => "application/cbor",
+            Format::MsgPack => "application/msgpack",
+        }
+    }
+
+    // Also accepts the structured +json suffix and the unregistered names clients commonly send for MessagePack
+    pub fn from_media_type(media_type: &str) -> Option<Format> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/json" => Some(Format::Json),
+            "application/cbor" => Some(Format::Cbor),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Some(Format::MsgPack),
+            _ if essence.starts_with("application/") && essence.ends_with("+json") => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    pub fn from_content_type(headers: &HeaderMap) -> Option<Format> {
+        headers.get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Format::from_media_type)
+    }
+
+    // A missing header accepts anything, which means JSON; None means the client accepts none of the formats
+    pub fn negotiate(headers: &HeaderMap) -> Option<Format> {
+        negotiate(headers, &Format::ALL, Format::content_type, |format, media_type| {
+            Format::from_media_type(media_type) == Some(format)
+        })
+    }
+
+    pub fn encode<T: serde::Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|error| error.to_string()),
+            Format::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::into_writer(value, &mut buffer).map_err(|error| error.to_string())?;
+                Ok(buffer)
+            }
+            // Named fields keep the document shaped like the JSON one instead of a positional array
+            Format::MsgPack => rmp_serde::to_vec_named(value).map_err(|error| error.to_string()),
+        }
+    }
+
+    // Decodes a binary body into a generic document, so the typed deserialization that follows can report
+    // field paths the same way for every format
+    pub fn decode_document(self, bytes: &[u8]) -> Result<serde_json::Value, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|error| error.to_string()),
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|error| error.to_string()),
+            Format::MsgPack => rmp_serde::from_slice(bytes).map_err(|error| error.to_string()),
+        }
+    }
+}
+
+// Picks the offer with the highest quality in the Accept header. content_type gives the media type of an offer and
+// is_media_type tells whether a media type, which may be an alias, names it. Each offer takes the quality of the
+// most specific range that matches it, so "*/*;q=0.1, application/cbor" prefers CBOR. A missing header or a tie
+// picks the earliest offer
+pub fn negotiate<T: Copy>(
+    headers: &HeaderMap,
+    offers: &[T],
+    content_type: impl Fn(T) -> &'static str,
+    is_media_type: impl Fn(T, &str) -> bool
+) -> Option<T> {
+    let ranges: Vec<(String, f32)> = headers.get_all(header::ACCEPT).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter(|range| !range.trim().is_empty())
+        .map(parse_range)
+        .collect();
+
+    if ranges.is_empty() {
+        return offers.first().copied();
+    }
+
+    let quality = |offer: T| {
+        let (kind, _) = content_type(offer).split_once('/').unwrap_or_default();
+        let wildcard = format!("{}/*", kind);
+        let specificity = |range: &str| match range {
+            _ if is_media_type(offer, range) => Some(2),
+            _ if range == wildcard => Some(1),
+            "*/*" => Some(0),
+            _ => None,
+        };
+
+        ranges.iter()
+            .filter_map(|(range, quality)| specificity(range).map(|specificity| (specificity, *quality)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, quality)| quality)
+            .unwrap_or(0.0)
+    };
+
+    offers.iter()
+        .map(|offer| (*offer, quality(*offer)))
+        .filter(|(_, quality)| *quality > 0.0)
+        // Keeps the earlier offer on ties, since max_by returns the last maximum
+        .rev()
+        .max_by(|(_, left), (_, right)| left.total_cmp(right))
+        .map(|(offer, _)| offer)
+}
+
+fn parse_range(range: &str) -> (String, f32) {
+    let mut parts = range.split(';').map(str::trim);
+    let media_range = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let quality = parts
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+        .unwrap_or(1.0);
+
+    (media_range, quality)
+}
+
+// - - - - - - - - - - - [EXTRACTORS] - - - - - - - - - - -
+
+// The format to encode the response in, negotiated from the Accept header. Rejects with 406 up front,
+// before the handler does any work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseFormat(pub Format);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = (StatusCode, Json<ErrorBody>);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Format::negotiate(&parts.headers).map(ResponseFormat).ok_or_else(|| {
+            let request_id = RequestId::from_headers(&parts.headers).unwrap_or_else(|| RequestId("unknown".to_string()));
+            let supported: Vec<&str> = Format::ALL.iter().map(|format| format.content_type()).collect();
+            let body = ErrorBody {
+                error: format!("None of the accepted media types is supported, expected one of {}", supported.join(", ")),
+                request_id: request_id.0,
+                field: None,
+            };
+            (StatusCode::NOT_ACCEPTABLE, Json(body))
+        })
+    }
+}
+
+// - - - - - - - - - - - [RESPONSES] - - - - - - - - - - -
+
+// A response body encoded in the negotiated format
+#[derive(Debug, Clone)]
+pub struct Encoded<T>(pub Format, pub T);
+
+impl<T: serde::Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Encoded(format, value) = self;
+
+        match format.encode(&value) {
+            Ok(bytes) => ([(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()))], bytes).into_response(),
+            Err(error) => {
+                tracing::error!(%error, content_type = format.content_type(), "failed to encode response");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_from_media_type() {
+        assert_eq!(Format::from_media_type("application/json; charset=utf-8"), Some(Format::Json));
+        assert_eq!(Format::from_media_type("application/merge-patch+json"), Some(Format::Json));
+        assert_eq!(Format::from_media_type("Application/CBOR"), Some(Format::Cbor));
+        assert_eq!(Format::from_media_type("application/vnd.msgpack"), Some(Format::MsgPack));
+        assert_eq!(Format::from_media_type("text/plain"), None);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Format::negotiate(&HeaderMap::new()), Some(Format::Json));
+        assert_eq!(Format::negotiate(&accept("*/*")), Some(Format::Json));
+        assert_eq!(Format::negotiate(&accept("application/cbor")), Some(Format::Cbor));
+        assert_eq!(Format::negotiate(&accept("application/x-msgpack")), Some(Format::MsgPack));
+        assert_eq!(Format::negotiate(&accept("application/json;q=0.5, application/msgpack")), Some(Format::MsgPack));
+        assert_eq!(Format::negotiate(&accept("*/*;q=0.1, application/cbor")), Some(Format::Cbor));
+        assert_eq!(Format::negotiate(&accept("application/*, application/json;q=0")), Some(Format::Cbor));
+        assert_eq!(Format::negotiate(&accept("text/html")), None);
+        assert_eq!(Format::negotiate(&accept("application/cbor;q=0")), None);
+    }
+
+    #[test]
+    fn test_round_trip_in_every_format() {
+        let body = ErrorBody { error: "No soup for you".to_string(), request_id: "soup".to_string(), field: None };
+
+        for format in Format::ALL {
+            let bytes = format.encode(&body).unwrap();
+            let document = format.decode_document(&bytes).unwrap();
+
+            assert_eq!(document["error"], "No soup for you", "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    sync::Arc,
+    time::Duration
+};
+use async_trait::async_trait;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+    Json
+};
+use futures::future::join_all;
+use serde_derive::Serialize;
+use serde_json::json;
+use crate::shutdown::Drain;
+
+// A check that takes longer than this is reported as failed so a hung dependency cannot hang the probe
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// - - - - - - - - - - - [CHECKS] - - - - - - - - - - -
+
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    fn name(&self) -> &str;
+
+    // Returns a human readable reason when the check fails
+    async fn check(&self) -> Result<(), String>;
+}
+
+pub struct DrainCheck(pub Drain);
+
+#[async_trait]
+impl HealthCheck for DrainCheck {
+    fn name(&self) -> &str {
+        "draining"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        if self.0.is_draining() {
+            Err("shutting down".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct HealthRegistry {
+    checks: Vec<Arc<dyn HealthCheck>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: &'static str,
+    pub checks: Vec<CheckResult>,
+}
+
+impl HealthReport {
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|check| check.error.is_none())
+    }
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        HealthRegistry::default()
+    }
+
+    pub fn register(&mut self, check: Arc<dyn HealthCheck>) -> &mut Self {
+        self.checks.push(check);
+        self
+    }
+
+    // Runs every registered check concurrently
+    pub async fn run(&self) -> HealthReport {
+        let results = join_all(self.checks.iter().map(|check| async move {
+            let outcome = match tokio::time::timeout(CHECK_TIMEOUT, check.check()).await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(format!("timed out after {:?}", CHECK_TIMEOUT)),
+            };
+            CheckResult {
+                name: check.name().to_string(),
+                status: if outcome.is_ok() { "ok" } else { "fail" },
+                error: outcome.err(),
+            }
+        })).await;
+
+        let ready = results.iter().all(|result| result.error.is_none());
+        HealthReport {
+            status: if ready { "ready" } else { "not_ready" },
+            checks: results,
+        }
+    }
+}
+
+// - - - - - - - - - - - [ROUTES] - - - - - - - - - - -
+
+pub fn health_routes(registry: HealthRegistry) -> Router {
+    Router::new()
+        .route("/healthz", get(liveness_handler))
+        .route("/readyz", get(readiness_handler))
+        .with_state(Arc::new(registry))
+}
+
+// - - - - - - - - - - - [HANDLERS] - - - - - - - - - - -
+
+pub async fn liveness_handler() -> impl IntoResponse {
+    (StatusCode::OK, Json(json!({"status": "alive"})))
+}
+
+pub async fn readiness_handler(State(registry): State<Arc<HealthRegistry>>) -> impl IntoResponse {
+    let report = registry.run().await;
+    let status = if report.is_ready() { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticCheck(&'static str, Result<(), String>);
+
+    #[async_trait]
+    impl HealthCheck for StaticCheck {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            self.1.clone()
+        }
+    }
+
+    struct HangingCheck;
+
+    #[async_trait]
+    impl HealthCheck for HangingCheck {
+        fn name(&self) -> &str {
+            "hanging"
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_registry_is_ready() {
+        let report = HealthRegistry::new().run().await;
+
+        assert!(report.is_ready());
+        assert_eq!(report.status, "ready");
+    }
+
+    #[tokio::test]
+    async fn test_single_failure_makes_report_not_ready() {
+        let mut registry = HealthRegistry::new();
+        registry
+            .register(Arc::new(StaticCheck("storage", Ok(()))))
+            .register(Arc::new(StaticCheck("cache", Err("connection refused".to_string()))));
+
+        let report = registry.run().await;
+
+        assert!(!report.is_ready());
+        assert_eq!(report.status, "not_ready");
+        assert_eq!(report.checks[0].status, "ok");
+        assert_eq!(report.checks[1].error.as_deref(), Some("connection refused"));
+    }
+
+    #[tokio::test]
+    async fn test_drain_check_fails_once_draining() {
+        let drain = Drain::new();
+        let check = DrainCheck(drain.clone());
+        assert!(check.check().await.is_ok());
+
+        drain.start();
+
+        assert!(check.check().await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hanging_check_times_out() {
+        let mut registry = HealthRegistry::new();
+        registry.register(Arc::new(HangingCheck));
+
+        let report = registry.run().await;
+
+        assert!(!report.is_ready());
+        assert!(report.checks[0].error.as_ref().unwrap().contains("timed out"));
+    }
+}
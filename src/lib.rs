@@ -1 +1,18 @@
+pub mod admin;
+pub mod api;
+pub mod cli;
+pub mod compression;
+pub mod config;
+pub mod cors;
+pub mod extract;
+pub mod format;
+pub mod health;
+pub mod metrics;
+pub mod openapi;
+pub mod rate_limit;
+pub mod request_id;
+pub mod server;
+pub mod shutdown;
+pub mod storage;
+pub mod telemetry;
 pub mod users;
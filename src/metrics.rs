@@ -0,0 +1,98 @@
+use std::{
+    sync::LazyLock,
+    time::Instant
+};
+use axum::{
+    extract::{MatchedPath, State},
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router
+};
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, TextEncoder
+};
+use crate::users::service::{count_users, UserStore};
+
+// Metrics live in the prometheus default registry, mirroring how tracing uses a global subscriber,
+// so the service layer can record store latencies without threading a handle through every call.
+// With the "process" feature the default registry also exports process_* metrics on Linux
+
+pub static HTTP_REQUESTS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("http_requests_total", "HTTP requests by route template and status", &["method", "path", "status"])
+        .expect("http_requests_total is registered once")
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!("http_request_duration_seconds", "HTTP request latency by route template and status", &["method", "path", "status"])
+        .expect("http_request_duration_seconds is registered once")
+});
+
+pub static RATE_LIMITED_REQUESTS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("rate_limited_requests_total", "Requests rejected with 429 by route limit", &["route"])
+        .expect("rate_limited_requests_total is registered once")
+});
+
+pub static STORE_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!("store_operation_duration_seconds", "Latency of users::service operations", &["operation"])
+        .expect("store_operation_duration_seconds is registered once")
+});
+
+// hit and miss; the hit ratio is rate of hit over the rate of both
+pub static USER_CACHE_REQUESTS_TOTAL: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("user_cache_requests_total", "Lookups of users by email in the cache by result", &["result"])
+        .expect("user_cache_requests_total is registered once")
+});
+
+pub static USERS_IN_STORE: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("users_in_store", "Number of users currently held by the store")
+        .expect("users_in_store is registered once")
+});
+
+// - - - - - - - - - - - [MIDDLEWARE] - - - - - - - - - - -
+
+// Labels use the route template so /users/:email does not create one series per email
+pub async fn track_http_metrics<B>(request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().to_string();
+    let path = request.extensions().get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| "<unmatched>".to_string());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let status = response.status().as_u16().to_string();
+
+    HTTP_REQUESTS_TOTAL.with_label_values(&[&method, &path, &status]).inc();
+    HTTP_REQUEST_DURATION.with_label_values(&[&method, &path, &status]).observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+// - - - - - - - - - - - [ROUTES] - - - - - - - - - - -
+
+pub fn metrics_routes(shared_hashmap: UserStore) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(shared_hashmap)
+}
+
+// - - - - - - - - - - - [HANDLERS] - - - - - - - - - - -
+
+pub async fn metrics_handler(State(shared_hashmap): State<UserStore>) -> impl IntoResponse {
+    // A backend that cannot be reached leaves the last count in place rather than failing the scrape. One that
+    // cannot count cheaply never registers the gauge, rather than reporting zero users
+    match count_users(&shared_hashmap).await {
+        Ok(Some(count)) => USERS_IN_STORE.set(count as i64),
+        Ok(None) => {}
+        Err(error) => tracing::warn!(%error, "failed to count users"),
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => (StatusCode::OK, [(header::CONTENT_TYPE, encoder.format_type().to_string())], buffer).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response(),
+    }
+}
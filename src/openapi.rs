@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+    Json
+};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::Config;
+use crate::users::{
+    model::{
+        BatchItemResult, BatchItemStatus, BatchMode, BatchReport, ErrorBody, ImportAction, ImportReport, ImportRowResult,
+        MessageBody, UpsertUser, User
+    },
+    router
+};
+
+// Generated from the handler annotations in users::router and the schemas of users::model,
+// so the document cannot be edited independently of the implementation
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Users API", description = "CRUD service for users"),
+    servers((url = "/v1", description = "Current version")),
+    paths(
+        router::create_user_handler,
+        router::create_users_handler,
+        router::export_users_handler,
+        router::import_users_handler,
+        router::get_user_handler,
+        router::update_user_handler,
+        router::delete_user_handler
+    ),
+    components(schemas(
+        User, UpsertUser, ErrorBody, MessageBody, BatchMode, BatchItemStatus, BatchItemResult, BatchReport,
+        ImportAction, ImportRowResult, ImportReport
+    )),
+    tags((name = "users", description = "User management"))
+)]
+pub struct ApiDoc;
+
+// - - - - - - - - - - - [ROUTES] - - - - - - - - - - -
+
+pub fn openapi_routes() -> Router {
+    Router::new().route("/openapi.json", get(openapi_handler))
+}
+
+// Swagger UI's assets are compiled into the binary, so the page works in the scratch image without network access
+pub fn docs_routes() -> Router {
+    let config = Arc::new(Config::from("/openapi.json"));
+
+    Router::new()
+        // The page loads its assets relative to the trailing slash
+        .route("/docs", get(|| async { Redirect::permanent("/docs/") }))
+        .route("/docs/", get(docs_index_handler))
+        .route("/docs/*file", get(docs_handler))
+        .with_state(config)
+}
+
+// - - - - - - - - - - - [HANDLERS] - - - - - - - - - - -
+
+pub async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub async fn docs_index_handler(State(config): State<Arc<Config<'static>>>) -> Response {
+    serve_docs_file("", config)
+}
+
+pub async fn docs_handler(Path(file): Path<String>, State(config): State<Arc<Config<'static>>>) -> Response {
+    serve_docs_file(&file, config)
+}
+
+fn serve_docs_file(file: &str, config: Arc<Config<'static>>) -> Response {
+    match utoipa_swagger_ui::serve(file, config) {
+        Ok(Some(file)) => ([(header::CONTENT_TYPE, file.content_type)], file.bytes.into_owned()).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(error) => {
+            tracing::error!(%error, "failed to render swagger ui");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration
+};
+use async_trait::async_trait;
+use tokio::time::Instant;
+use crate::{
+    config::LimitPolicy,
+    rate_limit::{Decision, RateLimitBackend}
+};
+
+// Waits are reported as at most this long, which also keeps full_at within what an Instant can hold when
+// a bucket refills very slowly
+const MAX_WAIT: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+// Buckets that have refilled completely carry no information and are dropped every this many acquisitions
+const SWEEP_INTERVAL: u64 = 1024;
+
+// Keeps buckets in the memory of this process, so each replica enforces its own limits
+#[derive(Default)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    buckets: HashMap<String, Bucket>,
+    acquisitions: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    full_at: Instant,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        MemoryBackend::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The buckets hold no invariant a panic could break halfway, so a poisoned lock is used as it is
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryBackend {
+    async fn acquire(&self, key: &str, policy: LimitPolicy) -> Result<Decision, String> {
+        let now = Instant::now();
+        let mut state = self.state();
+
+        state.acquisitions += 1;
+        if state.acquisitions.is_multiple_of(SWEEP_INTERVAL) {
+            state.buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        let bucket = state.buckets.entry(key.to_string()).or_insert_with(|| Bucket {
+            tokens: policy.capacity as f64,
+            updated: now,
+            full_at: now,
+        });
+        Ok(bucket.take(policy, now))
+    }
+}
+
+impl Bucket {
+    fn take(&mut self, policy: LimitPolicy, now: Instant) -> Decision {
+        let capacity = policy.capacity as f64;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * policy.refill_per_sec).min(capacity);
+        self.updated = now;
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+
+        let reset = wait((capacity - self.tokens) / policy.refill_per_sec);
+        self.full_at = now + reset;
+
+        Decision {
+            allowed,
+            limit: policy.capacity,
+            remaining: self.tokens.floor() as u32,
+            reset,
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                wait((1.0 - self.tokens) / policy.refill_per_sec)
+            },
+        }
+    }
+}
+
+fn wait(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds).unwrap_or(MAX_WAIT).min(MAX_WAIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: LimitPolicy = LimitPolicy { capacity: 3, refill_per_sec: 0.5 };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_allows_burst_up_to_capacity() {
+        let backend = MemoryBackend::new();
+
+        for remaining in [2, 1, 0] {
+            let decision = backend.acquire("ip:127.0.0.1", POLICY).await.unwrap();
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+
+        let decision = backend.acquire("ip:127.0.0.1", POLICY).await.unwrap();
+        assert!(!decision.allowed);
+        assert_eq!(decision.limit, 3);
+        assert_eq!(decision.retry_after, Duration::from_secs(2));
+        assert_eq!(decision.reset, Duration::from_secs(6));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_refills_over_time() {
+        let backend = MemoryBackend::new();
+        for _ in 0..3 {
+            backend.acquire("ip:127.0.0.1", POLICY).await.unwrap();
+        }
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+
+        assert!(backend.acquire("ip:127.0.0.1", POLICY).await.unwrap().allowed);
+        assert!(!backend.acquire("ip:127.0.0.1", POLICY).await.unwrap().allowed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keys_have_separate_buckets() {
+        let backend = MemoryBackend::new();
+        for _ in 0..3 {
+            backend.acquire("api_key:0", POLICY).await.unwrap();
+        }
+
+        assert!(!backend.acquire("api_key:0", POLICY).await.unwrap().allowed);
+        assert!(backend.acquire("api_key:1", POLICY).await.unwrap().allowed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tiny_refill_rate_does_not_overflow() {
+        let backend = MemoryBackend::new();
+        let policy = LimitPolicy { capacity: 1, refill_per_sec: f64::MIN_POSITIVE };
+
+        assert!(backend.acquire("ip:127.0.0.1", policy).await.unwrap().allowed);
+        let decision = backend.acquire("ip:127.0.0.1", policy).await.unwrap();
+
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, MAX_WAIT);
+        assert_eq!(decision.reset, MAX_WAIT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_full_buckets_are_swept() {
+        let backend = MemoryBackend::new();
+        backend.acquire("ip:10.0.0.1", POLICY).await.unwrap();
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        for _ in 1..SWEEP_INTERVAL {
+            backend.acquire("ip:10.0.0.2", POLICY).await.unwrap();
+        }
+
+        // Only the bucket that is still being drained survives the sweep
+        assert_eq!(backend.len(), 1);
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, MatchedPath, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+    Json
+};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use crate::{
+    api::unversioned_path,
+    config::{LimitPolicy, RateLimitConfig, Secret},
+    metrics::RATE_LIMITED_REQUESTS_TOTAL,
+    request_id::{RequestId, REQUEST_ID_HEADER},
+    users::model::ErrorBody
+};
+
+pub mod memory;
+
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+// draft-ietf-httpapi-ratelimit-headers
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+// - - - - - - - - - - - [BACKENDS] - - - - - - - - - - -
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    // Time until the bucket is full again
+    pub reset: Duration,
+    // Time until the next request would be allowed, zero when this one was
+    pub retry_after: Duration,
+}
+
+// Stores the token buckets. A backend shared between replicas, such as Redis, makes the limits hold for the
+// service as a whole instead of per container
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    // Takes one token from the bucket identified by `key`, which starts out full
+    async fn acquire(&self, key: &str, policy: LimitPolicy) -> Result<Decision, String>;
+}
+
+// - - - - - - - - - - - [CLIENTS] - - - - - - - - - - -
+
+// Who a bucket belongs to, from most to least specific
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientKey {
+    // Position of the key in auth.api_keys, so the secret itself never becomes a bucket key
+    ApiKey(usize),
+    Ip(IpAddr),
+    Unknown,
+}
+
+impl fmt::Display for ClientKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientKey::ApiKey(index) => write!(f, "api_key:{}", index),
+            ClientKey::Ip(ip) => write!(f, "ip:{}", ip),
+            ClientKey::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+// Walks X-Forwarded-For from the right, skipping trusted proxies, and returns the first address that was not
+// added by one of them. Entries left of that address are supplied by the client and cannot be believed
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let mut candidate = peer?;
+    if !trusted_proxies.contains(&candidate) {
+        return Some(candidate);
+    }
+
+    let forwarded: Vec<&str> = headers.get_all("x-forwarded-for").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    for entry in forwarded.into_iter().rev() {
+        match entry.parse::<IpAddr>() {
+            Ok(ip) if trusted_proxies.contains(&ip) => candidate = ip,
+            Ok(ip) => return Some(ip),
+            Err(_) => break,
+        }
+    }
+
+    Some(candidate)
+}
+
+// - - - - - - - - - - - [LIMITER] - - - - - - - - - - -
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    api_keys: Vec<Secret>,
+    backend: Arc<dyn RateLimitBackend>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig, api_keys: Vec<Secret>, backend: Arc<dyn RateLimitBackend>) -> Self {
+        RateLimiter { config, api_keys, backend }
+    }
+
+    // An API key that is not configured is ignored rather than trusted, otherwise a client could mint
+    // a fresh bucket per request by sending random keys
+    pub fn client_key<B>(&self, request: &Request<B>) -> ClientKey {
+        let api_key = request.headers().get(API_KEY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|key| self.api_keys.iter().position(|known| known.matches(key)));
+        if let Some(index) = api_key {
+            return ClientKey::ApiKey(index);
+        }
+
+        let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+        match client_ip(peer, request.headers(), &self.config.trusted_proxies) {
+            Some(ip) => ClientKey::Ip(ip),
+            None => ClientKey::Unknown,
+        }
+    }
+
+    // Returns the name of the bucket the route draws from along with its policy
+    pub fn route_policy<B>(&self, request: &Request<B>) -> (String, LimitPolicy) {
+        let path = request.extensions().get::<MatchedPath>()
+            .map(|matched| unversioned_path(matched.as_str()));
+
+        self.config.routes.iter()
+            .find(|route| Some(route.path.as_str()) == path && route.method.eq_ignore_ascii_case(request.method().as_str()))
+            .map(|route| (format!("{} {}", route.method.to_uppercase(), route.path), route.policy()))
+            .unwrap_or_else(|| ("default".to_string(), self.config.default))
+    }
+}
+
+// - - - - - - - - - - - [MIDDLEWARE] - - - - - - - - - - -
+
+// Request ids are assigned outside the limiter so rejected requests carry one as well. The layers inside
+// the routes keep the id that is already present
+pub fn rate_limited(routes: Router, limiter: Arc<RateLimiter>) -> Router {
+    routes
+        .layer(middleware::from_fn_with_state(limiter, rate_limit))
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+}
+
+pub async fn rate_limit<B>(State(limiter): State<Arc<RateLimiter>>, request: Request<B>, next: Next<B>) -> Response {
+    let client = limiter.client_key(&request);
+    let (route, policy) = limiter.route_policy(&request);
+
+    // A backend that cannot be reached must not take the API down with it
+    let decision = match limiter.backend.acquire(&format!("{}|{}", client, route), policy).await {
+        Ok(decision) => decision,
+        Err(error) => {
+            tracing::warn!(%error, "rate limit backend unavailable, letting request through");
+            return next.run(request).await;
+        }
+    };
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        RATE_LIMITED_REQUESTS_TOTAL.with_label_values(&[&route]).inc();
+        tracing::info!(route = %route, "rate limit exceeded");
+
+        let request_id = RequestId::from_headers(request.headers()).unwrap_or_else(|| RequestId("unknown".to_string()));
+        let body = ErrorBody { error: "Too many requests".to_string(), request_id: request_id.0, field: None };
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, seconds(decision.retry_after.max(Duration::from_secs(1))));
+        response
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, seconds(decision.reset));
+    response
+}
+
+// Header values are whole seconds, rounded up so clients never retry too early
+fn seconds(duration: Duration) -> HeaderValue {
+    HeaderValue::from(duration.as_secs_f64().ceil() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROXY: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_forwarded_for_is_ignored_from_untrusted_peer() {
+        let peer = "203.0.113.7".parse().unwrap();
+
+        let ip = client_ip(Some(peer), &forwarded_for("198.51.100.1"), &[PROXY]);
+
+        assert_eq!(ip, Some(peer));
+    }
+
+    #[test]
+    fn test_forwarded_for_is_used_from_trusted_proxy() {
+        let ip = client_ip(Some(PROXY), &forwarded_for("192.0.2.66, 198.51.100.1"), &[PROXY]);
+
+        // The left entry was sent by the client and could be anything
+        assert_eq!(ip, Some("198.51.100.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_chained_trusted_proxies_are_skipped() {
+        let inner: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let ip = client_ip(Some(PROXY), &forwarded_for("198.51.100.1, 10.0.0.2"), &[PROXY, inner]);
+
+        assert_eq!(ip, Some("198.51.100.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_malformed_forwarded_for_falls_back_to_proxy() {
+        assert_eq!(client_ip(Some(PROXY), &forwarded_for("hello newman"), &[PROXY]), Some(PROXY));
+        assert_eq!(client_ip(Some(PROXY), &HeaderMap::new(), &[PROXY]), Some(PROXY));
+        assert_eq!(client_ip(None, &forwarded_for("198.51.100.1"), &[PROXY]), None);
+    }
+}
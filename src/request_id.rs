@@ -0,0 +1,45 @@
+use std::convert::Infallible;
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, HeaderName}
+};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// The id assigned to the current request, either taken from an incoming X-Request-Id header or generated by
+// SetRequestIdLayer. Extracting it never fails so handlers can always include it in error bodies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn from_headers(headers: &http::HeaderMap) -> Option<RequestId> {
+        headers.get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| RequestId(value.to_string()))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestId {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestId::from_headers(&parts.headers).unwrap_or_else(|| RequestId("unknown".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_headers() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(RequestId::from_headers(&headers), None);
+
+        headers.insert(REQUEST_ID_HEADER, "yada-yada".parse().unwrap());
+
+        assert_eq!(RequestId::from_headers(&headers), Some(RequestId("yada-yada".to_string())));
+    }
+}
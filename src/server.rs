@@ -0,0 +1,127 @@
+use std::{
+    convert::Infallible,
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration
+};
+use axum::{
+    body::Body,
+    extract::DefaultBodyLimit,
+    http::Request,
+    response::Response,
+    ServiceExt
+};
+use tower::{Layer, Service, util::MapRequestLayer};
+use crate::{
+    admin::{admin_routes, AdminState},
+    api::api_routes,
+    cli::CliError,
+    compression::compressed,
+    config::Config,
+    cors::cors_layer,
+    health::{health_routes, DrainCheck, HealthRegistry},
+    metrics::metrics_routes,
+    openapi::{docs_routes, openapi_routes},
+    rate_limit::{memory::MemoryBackend, rate_limited, RateLimiter},
+    shutdown::{self, Drain},
+    storage::{self, health::storage_checks, snapshot},
+    users::router::{rewrite_batch_alias, users_routes}
+};
+
+pub async fn serve(config: Config) -> Result<(), CliError> {
+    let store = storage::open(&config.storage).await?;
+    let drain = Drain::new();
+
+    let mut registry = HealthRegistry::new();
+    registry.register(Arc::new(DrainCheck(drain.clone())));
+    for check in storage_checks(&store) {
+        registry.register(check);
+    }
+
+    let mut api = compressed(api_routes(&config.api, [("v1", users_routes(store.clone()))]), &config.compression);
+    if config.rate_limit.enabled {
+        let limiter = RateLimiter::new(config.rate_limit.clone(), config.auth.api_keys.clone(), Arc::new(MemoryBackend::new()));
+        api = rate_limited(api, Arc::new(limiter));
+    }
+
+    // Probes, metrics and docs are not rate limited so the platform can always reach them
+    let mut app = api
+        .merge(health_routes(registry))
+        .merge(metrics_routes(store.clone()))
+        .merge(openapi_routes());
+
+    if config.docs.enabled {
+        app = app.merge(docs_routes());
+    }
+
+    // Without a token the admin endpoints do not exist at all
+    if let Some(admin_token) = config.auth.admin_token.clone() {
+        app = app.merge(admin_routes(admin_token, AdminState { storage: config.storage.clone(), store: store.clone() }));
+    }
+
+    // CORS is outermost so preflight requests are answered before reaching any route
+    let app = app
+        .layer(DefaultBodyLimit::max(config.limits.max_body_bytes))
+        .layer(cors_layer(&config.cors));
+    // Layers on a router run after routing, so the rewrite wraps the whole router instead
+    let app = MapRequestLayer::new(rewrite_batch_alias::<Body>).layer(app);
+
+    let listener = TcpListener::bind(config.socket_addr())?;
+    tracing::info!(addr = %listener.local_addr()?, "listening");
+
+    let signalled = drain.clone();
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        tracing::info!("shutdown signal received, draining connections");
+        signalled.start();
+    });
+
+    let snapshots = snapshot::spawn_periodic(&config.storage.snapshot, &store);
+
+    let pre_stop_delay = Duration::from_secs(config.server.pre_stop_delay_secs);
+    serve_until_drained(listener, app, &drain, pre_stop_delay, Duration::from_secs(config.server.drain_timeout_secs)).await?;
+
+    if let Some(snapshots) = snapshots {
+        snapshots.stop().await;
+    }
+    // Flush only after the last request has been handled so no accepted write is lost
+    storage::flush(&config.storage, &store).await?;
+    tracing::info!("shutdown complete");
+    Ok(())
+}
+
+// Serves until draining starts, keeps accepting connections for `pre_stop_delay` while readiness reports 503,
+// then stops accepting and waits at most `drain_timeout` for in-flight requests before returning
+pub async fn serve_until_drained<S>(
+    listener: TcpListener,
+    app: S,
+    drain: &Drain,
+    pre_stop_delay: Duration,
+    drain_timeout: Duration
+) -> Result<(), hyper::Error>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static
+{
+    let draining = drain.wait();
+    let stop_accepting = async move {
+        draining.await;
+        tokio::time::sleep(pre_stop_delay).await;
+        tracing::info!("pre-stop delay elapsed, no longer accepting connections");
+    };
+
+    let server = axum::Server::from_tcp(listener)?
+        // The peer address keys rate limits for clients that are not behind a trusted proxy
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(stop_accepting);
+    tokio::pin!(server);
+
+    let deadline = drain.wait();
+    tokio::select! {
+        result = &mut server => result,
+        _ = async { deadline.await; tokio::time::sleep(pre_stop_delay + drain_timeout).await } => {
+            tracing::warn!(?drain_timeout, "drain timeout elapsed, dropping remaining connections");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    future::Future,
+    sync::Arc
+};
+use tokio::sync::watch;
+
+// Shared flag flipped as soon as a shutdown signal arrives. Readiness reports not-ready
+// from that moment while in-flight requests are allowed to finish
+#[derive(Debug, Clone)]
+pub struct Drain {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        let (sender, _receiver) = watch::channel(false);
+        Drain { sender: Arc::new(sender) }
+    }
+}
+
+impl Drain {
+    pub fn new() -> Self {
+        Drain::default()
+    }
+
+    pub fn start(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    // Resolves once draining has started
+    pub fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut receiver = self.sender.subscribe();
+        async move {
+            // The sender lives as long as any Drain clone, so an error here means nobody can ever start draining
+            if receiver.wait_for(|draining| *draining).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+// Resolves on SIGINT (Ctrl+C) or, on Unix, SIGTERM which is what ACI sends when stopping a container
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_drain_flips_immediately() {
+        let drain = Drain::new();
+        assert!(!drain.is_draining());
+
+        drain.clone().start();
+
+        assert!(drain.is_draining());
+    }
+
+    #[tokio::test]
+    async fn test_wait_resolves_after_start() {
+        let drain = Drain::new();
+        let waiter = tokio::spawn(drain.wait());
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        drain.start();
+
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_wait_resolves_when_already_draining() {
+        let drain = Drain::new();
+        drain.start();
+
+        tokio::time::timeout(Duration::from_secs(1), drain.wait()).await.unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration
+};
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde_derive::{Serialize, Deserialize};
+use tokio::time::Instant;
+use crate::{
+    config::{CacheBackend, CacheConfig},
+    metrics::USER_CACHE_REQUESTS_TOTAL,
+    storage::{PageCursor, RepositoryError, StorageError, UserPage, UserRepository},
+    users::{model::{User, UpsertUser}, service::BatchOutcome}
+};
+
+// A user as the cache keeps it. The password stays in the backend, so a cache shared with other readers never
+// holds credentials
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedProfile {
+    pub id: i32,
+    pub email: String,
+    pub fullname: String,
+    pub role: String,
+}
+
+impl From<User> for CachedProfile {
+    fn from(user: User) -> Self {
+        CachedProfile { id: user.id, email: user.email, fullname: user.fullname, role: user.role }
+    }
+}
+
+// What the cache knows about an email
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CachedUser {
+    Found { user: CachedProfile },
+    // The backend had no user with the email when asked
+    Missing,
+}
+
+impl CachedUser {
+    // The password comes back empty
+    fn into_user(self) -> Option<User> {
+        match self {
+            CachedUser::Found { user } => Some(User {
+                id: user.id,
+                email: user.email,
+                password: String::new(),
+                fullname: user.fullname,
+                role: user.role,
+            }),
+            CachedUser::Missing => None,
+        }
+    }
+}
+
+// Keeps users by the email they were asked for, as the backend normalizes it. Failures are logged by the caller,
+// which then asks the backend, so a cache that cannot be reached slows requests down rather than failing them
+#[async_trait]
+pub trait UserCache: Send + Sync {
+    async fn get(&self, email: &str) -> Result<Option<CachedUser>, String>;
+
+    async fn put(&self, email: &str, entry: CachedUser, ttl: Duration) -> Result<(), String>;
+
+    async fn invalidate(&self, email: &str) -> Result<(), String>;
+}
+
+// - - - - - - - - - - - [DECORATOR] - - - - - - - - - - -
+
+// Reads users through the cache and drops them from it on every write. Users read through it have an empty password,
+// whether or not the cache had them. A read racing a write may put the old user back after the write dropped it,
+// which then lasts until ttl runs out
+pub struct CachedRepository {
+    repository: Arc<dyn UserRepository>,
+    cache: Arc<dyn UserCache>,
+    ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl CachedRepository {
+    pub fn new(repository: Arc<dyn UserRepository>, cache: Arc<dyn UserCache>, config: &CacheConfig) -> Self {
+        CachedRepository {
+            repository,
+            cache,
+            ttl: Duration::from_secs(config.ttl_secs),
+            negative_ttl: Duration::from_secs(config.negative_ttl_secs),
+        }
+    }
+
+    // Puts the configured cache in front of the repository
+    pub async fn open(repository: Arc<dyn UserRepository>, config: &CacheConfig) -> Result<Self, StorageError> {
+        let cache: Arc<dyn UserCache> = match config.backend {
+            CacheBackend::Memory => Arc::new(LruUserCache::new(config.capacity)),
+            CacheBackend::Redis => {
+                let url = config.redis_url.as_ref().map(|url| url.expose()).unwrap_or_default();
+                Arc::new(RedisUserCache::connect(url, &config.key_prefix).await?)
+            }
+        };
+        Ok(CachedRepository::new(repository, cache, config))
+    }
+
+    async fn invalidate<'a>(&self, emails: impl IntoIterator<Item = &'a str>) {
+        for email in emails {
+            if let Err(error) = self.cache.invalidate(&self.repository.normalize_email(email)).await {
+                tracing::warn!(%error, "failed to drop user from cache");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl UserRepository for CachedRepository {
+    async fn create(&self, request: UpsertUser) -> Result<User, RepositoryError> {
+        let email = request.email.clone();
+        let user = self.repository.create(request).await?;
+        // Drops the "no such user" remembered for the email
+        self.invalidate([email.as_str(), user.email.as_str()]).await;
+        Ok(user)
+    }
+
+    async fn create_many(&self, requests: Vec<UpsertUser>, all_or_nothing: bool) -> Result<Vec<BatchOutcome>, RepositoryError> {
+        let outcomes = self.repository.create_many(requests, all_or_nothing).await?;
+        let created: Vec<&str> = outcomes.iter()
+            .filter_map(|outcome| match outcome {
+                BatchOutcome::Created(user) => Some(user.email.as_str()),
+                _ => None,
+            })
+            .collect();
+        self.invalidate(created).await;
+        Ok(outcomes)
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        let key = self.repository.normalize_email(email);
+        match self.cache.get(&key).await {
+            Ok(Some(entry)) => {
+                USER_CACHE_REQUESTS_TOTAL.with_label_values(&["hit"]).inc();
+                return Ok(entry.into_user());
+            }
+            Ok(None) => {}
+            Err(error) => tracing::warn!(%error, "failed to read user from cache"),
+        }
+        USER_CACHE_REQUESTS_TOTAL.with_label_values(&["miss"]).inc();
+
+        let (entry, ttl) = match self.repository.get(email).await? {
+            Some(user) => (CachedUser::Found { user: user.into() }, self.ttl),
+            None => (CachedUser::Missing, self.negative_ttl),
+        };
+        if !ttl.is_zero() {
+            if let Err(error) = self.cache.put(&key, entry.clone(), ttl).await {
+                tracing::warn!(%error, "failed to write user to cache");
+            }
+        }
+        Ok(entry.into_user())
+    }
+
+    async fn update(&self, email: &str, request: UpsertUser) -> Result<Option<User>, RepositoryError> {
+        let user = self.repository.update(email, request).await?;
+        if let Some(user) = &user {
+            self.invalidate([email, user.email.as_str()]).await;
+        }
+        Ok(user)
+    }
+
+    async fn delete(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        let user = self.repository.delete(email).await?;
+        if let Some(user) = &user {
+            self.invalidate([email, user.email.as_str()]).await;
+        }
+        Ok(user)
+    }
+
+    async fn list_page(&self, cursor: Option<&PageCursor>, limit: usize) -> Result<UserPage, RepositoryError> {
+        self.repository.list_page(cursor, limit).await
+    }
+
+    async fn list_all(&self) -> Result<Vec<User>, RepositoryError> {
+        self.repository.list_all().await
+    }
+
+    async fn count(&self) -> Result<Option<usize>, RepositoryError> {
+        self.repository.count().await
+    }
+
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        self.repository.ping().await
+    }
+
+    fn normalize_email(&self, email: &str) -> String {
+        self.repository.normalize_email(email)
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<String>, RepositoryError> {
+        self.repository.pending_migrations().await
+    }
+}
+
+// - - - - - - - - - - - [CACHES] - - - - - - - - - - -
+
+// Holds at most capacity users in the memory of this process, evicting the least recently used
+pub struct LruUserCache {
+    entries: Mutex<lru::LruCache<String, (CachedUser, Instant)>>,
+}
+
+impl LruUserCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        LruUserCache { entries: Mutex::new(lru::LruCache::new(capacity)) }
+    }
+
+    // Every entry is a copy the backend can give again, so a poisoned lock is used as it is
+    fn entries(&self) -> MutexGuard<'_, lru::LruCache<String, (CachedUser, Instant)>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl UserCache for LruUserCache {
+    async fn get(&self, email: &str) -> Result<Option<CachedUser>, String> {
+        let mut entries = self.entries();
+        match entries.get(email) {
+            Some((entry, expires_at)) if *expires_at > Instant::now() => Ok(Some(entry.clone())),
+            Some(_) => {
+                entries.pop(email);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, email: &str, entry: CachedUser, ttl: Duration) -> Result<(), String> {
+        self.entries().put(email.to_string(), (entry, Instant::now() + ttl));
+        Ok(())
+    }
+
+    async fn invalidate(&self, email: &str) -> Result<(), String> {
+        self.entries().pop(email);
+        Ok(())
+    }
+}
+
+// Keeps users in Redis as JSON, expiring them there, so every replica sees the same entries and a write on one
+// replica drops the user for all of them
+pub struct RedisUserCache {
+    connection: ConnectionManager,
+    key_prefix: String,
+}
+
+impl RedisUserCache {
+    // Connects up front so that a wrong URL fails the start, and reconnects on its own after that
+    pub async fn connect(url: &str, key_prefix: &str) -> Result<Self, StorageError> {
+        let config = redis::aio::ConnectionManagerConfig::new()
+            .set_connection_timeout(Duration::from_secs(2))
+            .set_response_timeout(Duration::from_secs(1))
+            .set_number_of_retries(1);
+        let connection = redis::Client::open(url)?.get_connection_manager_with_config(config).await?;
+        Ok(RedisUserCache { connection, key_prefix: key_prefix.to_string() })
+    }
+
+    fn key(&self, email: &str) -> String {
+        format!("{}{}", self.key_prefix, email)
+    }
+}
+
+#[async_trait]
+impl UserCache for RedisUserCache {
+    async fn get(&self, email: &str) -> Result<Option<CachedUser>, String> {
+        let value: Option<String> = self.connection.clone().get(self.key(email)).await.map_err(|error| error.to_string())?;
+        value.map(|value| serde_json::from_str(&value)).transpose().map_err(|error| error.to_string())
+    }
+
+    async fn put(&self, email: &str, entry: CachedUser, ttl: Duration) -> Result<(), String> {
+        let value = serde_json::to_string(&entry).map_err(|error| error.to_string())?;
+        self.connection.clone()
+            .set_ex::<_, _, ()>(self.key(email), value, ttl.as_secs().max(1))
+            .await
+            .map_err(|error| error.to_string())
+    }
+
+    async fn invalidate(&self, email: &str) -> Result<(), String> {
+        self.connection.clone().del::<_, ()>(self.key(email)).await.map_err(|error| error.to_string())
+    }
+}
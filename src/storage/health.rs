@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use crate::{
+    health::HealthCheck,
+    users::service::UserStore
+};
+
+pub struct RepositoryCheck(pub UserStore);
+
+#[async_trait]
+impl HealthCheck for RepositoryCheck {
+    fn name(&self) -> &str {
+        "storage"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.repository().ping().await.map_err(|error| error.to_string())
+    }
+}
+
+pub struct JournalCheck(pub UserStore);
+
+#[async_trait]
+impl HealthCheck for JournalCheck {
+    fn name(&self) -> &str {
+        "journal"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.memory().and_then(|memory| memory.journal()).map_or(Ok(()), |journal| journal.check())
+    }
+}
+
+pub struct MigrationsCheck(pub UserStore);
+
+#[async_trait]
+impl HealthCheck for MigrationsCheck {
+    fn name(&self) -> &str {
+        "migrations"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let pending = self.0.repository().pending_migrations().await.map_err(|error| error.to_string())?;
+        if pending.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("pending migrations: {}", pending.join(", ")))
+        }
+    }
+}
+
+pub fn storage_checks(store: &UserStore) -> Vec<Arc<dyn HealthCheck>> {
+    let mut checks: Vec<Arc<dyn HealthCheck>> = vec![Arc::new(RepositoryCheck(store.clone()))];
+    if store.memory().and_then(|memory| memory.journal()).is_some() {
+        checks.push(Arc::new(JournalCheck(store.clone())));
+    }
+    checks.push(Arc::new(MigrationsCheck(store.clone())));
+    checks
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc
+};
+use async_trait::async_trait;
+use serde_derive::{Serialize, Deserialize};
+use tokio::sync::{RwLock, RwLockReadGuard};
+use crate::{
+    storage::{PageCursor, RepositoryError, UserPage, UserRepository},
+    users::{model::{User, UpsertUser}, service::BatchOutcome}
+};
+
+// A change to the store as recorded in a journal. Each carries the resulting state rather than the request,
+// so applying a change twice has the same effect as applying it once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    Create { user: User },
+    Update { user: User },
+    Delete { email: String },
+}
+
+impl Change {
+    pub fn apply(self, users: &mut HashMap<String, User>) {
+        match self {
+            Change::Create { user } | Change::Update { user } => {
+                users.insert(user.email.clone(), user);
+            }
+            Change::Delete { email } => {
+                users.remove(&email);
+            }
+        }
+    }
+}
+
+// Durable record of the changes made to a store, such as the write-ahead log of storage::wal
+#[async_trait]
+pub trait Journal: Send + Sync {
+    // Called under the write lock before the changes are applied, so changes are recorded in the order they happen
+    async fn record(&self, changes: &[Change]) -> Result<(), String>;
+
+    // Forces every recorded change to durable storage
+    async fn sync(&self) -> Result<(), String>;
+
+    // Sets the changes recorded so far aside and records later ones apart from them. Called while no change can
+    // be made, so a snapshot taken at the same time covers exactly the changes set aside
+    async fn rotate(&self) -> Result<(), String>;
+
+    // Forgets the changes set aside by the last rotation, once a snapshot covers them
+    async fn remove_rotated(&self) -> Result<(), String>;
+
+    // Fails while changes are refused
+    fn check(&self) -> Result<(), String>;
+}
+
+// Keeps users in the memory of this process. A tokio RwLock lets concurrent reads proceed in parallel and,
+// unlike std::sync::Mutex, cannot be poisoned. Emails are compared exactly as given
+#[derive(Default)]
+pub struct MemoryRepository {
+    users: RwLock<HashMap<String, User>>,
+    journal: Option<Arc<dyn Journal>>,
+}
+
+impl MemoryRepository {
+    pub fn new(users: HashMap<String, User>) -> Self {
+        MemoryRepository { users: RwLock::new(users), journal: None }
+    }
+
+    // Records every change in the journal
+    pub fn with_journal(mut self, journal: Arc<dyn Journal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    pub fn journal(&self) -> Option<&Arc<dyn Journal>> {
+        self.journal.as_ref()
+    }
+
+    // Holding the guard also keeps every change out, which is what lets a snapshot line up with a journal rotation
+    pub async fn read(&self) -> RwLockReadGuard<'_, HashMap<String, User>> {
+        self.users.read().await
+    }
+
+    // Changes the journal cannot hold are refused, since applying them would lose them on the next start while the
+    // client was told they succeeded
+    async fn record(&self, changes: &[Change]) -> Result<(), RepositoryError> {
+        let Some(journal) = self.journal.as_ref().filter(|_| !changes.is_empty()) else {
+            return Ok(());
+        };
+        journal.record(changes).await.map_err(|error| {
+            tracing::error!(%error, "failed to record change in journal");
+            RepositoryError::Backend(error)
+        })
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn create(&self, request: UpsertUser) -> Result<User, RepositoryError> {
+        let mut acquired_map = self.users.write().await;
+        if acquired_map.contains_key(&request.email) {
+            return Err(RepositoryError::Duplicate);
+        }
+
+        let new_user = User {
+            id: (acquired_map.len() as i32) + 1,
+            email: request.email.clone(),
+            password: request.password,
+            fullname: request.fullname,
+            role: request.role,
+        };
+        self.record(&[Change::Create { user: new_user.clone() }]).await?;
+        acquired_map.insert(request.email, new_user.clone());
+        Ok(new_user)
+    }
+
+    // Runs under a single write lock, so no other write interleaves with the batch
+    async fn create_many(&self, requests: Vec<UpsertUser>, all_or_nothing: bool) -> Result<Vec<BatchOutcome>, RepositoryError> {
+        let mut acquired_map = self.users.write().await;
+
+        let mut seen = HashSet::new();
+        let duplicates: Vec<bool> = requests.iter()
+            .map(|request| acquired_map.contains_key(&request.email) || !seen.insert(request.email.clone()))
+            .collect();
+
+        if all_or_nothing && duplicates.contains(&true) {
+            return Ok(duplicates.into_iter()
+                .map(|duplicate| if duplicate { BatchOutcome::Duplicate } else { BatchOutcome::Skipped })
+                .collect());
+        }
+
+        // Recorded together, so the whole batch waits for a single fsync
+        let mut changes = Vec::new();
+        let outcomes = requests.into_iter().zip(duplicates).map(|(request, duplicate)| {
+            if duplicate {
+                return BatchOutcome::Duplicate;
+            }
+            let new_user = User {
+                id: (acquired_map.len() + changes.len()) as i32 + 1,
+                email: request.email,
+                password: request.password,
+                fullname: request.fullname,
+                role: request.role,
+            };
+            changes.push(Change::Create { user: new_user.clone() });
+            BatchOutcome::Created(new_user)
+        }).collect();
+
+        self.record(&changes).await?;
+        for change in changes {
+            change.apply(&mut acquired_map);
+        }
+        Ok(outcomes)
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        Ok(self.users.read().await.get(email).cloned())
+    }
+
+    async fn update(&self, email: &str, request: UpsertUser) -> Result<Option<User>, RepositoryError> {
+        let mut acquired_map = self.users.write().await;
+        let Some(user) = acquired_map.get(email) else {
+            return Ok(None);
+        };
+
+        let updated_user = User {
+            id: user.id,
+            email: user.email.clone(),
+            password: request.password,
+            fullname: request.fullname,
+            role: request.role,
+        };
+        self.record(&[Change::Update { user: updated_user.clone() }]).await?;
+        acquired_map.insert(email.to_string(), updated_user.clone());
+        Ok(Some(updated_user))
+    }
+
+    async fn delete(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        let mut acquired_map = self.users.write().await;
+        if !acquired_map.contains_key(email) {
+            return Ok(None);
+        }
+
+        self.record(&[Change::Delete { email: email.to_string() }]).await?;
+        Ok(acquired_map.remove(email))
+    }
+
+    async fn list_page(&self, cursor: Option<&PageCursor>, limit: usize) -> Result<UserPage, RepositoryError> {
+        let acquired_map = self.users.read().await;
+
+        let mut emails: Vec<&String> = acquired_map.keys()
+            .filter(|email| cursor.is_none_or(|PageCursor(after)| *email > after))
+            .collect();
+        if emails.len() > limit {
+            emails.select_nth_unstable(limit);
+            emails.truncate(limit);
+        }
+        emails.sort_unstable();
+
+        Ok(UserPage::by_email(emails.into_iter().map(|email| acquired_map[email].clone()).collect(), limit))
+    }
+
+    async fn list_all(&self) -> Result<Vec<User>, RepositoryError> {
+        let acquired_map = self.users.read().await;
+        let mut users: Vec<User> = acquired_map.values().cloned().collect();
+        users.sort_by_key(|user| user.id);
+        Ok(users)
+    }
+
+    async fn count(&self) -> Result<Option<usize>, RepositoryError> {
+        Ok(Some(self.users.read().await.len()))
+    }
+
+    // Fails through the registry timeout if a writer holds the lock indefinitely
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        let _acquired_map = self.users.read().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io,
+    path::PathBuf,
+    sync::Arc
+};
+use async_trait::async_trait;
+use crate::{
+    config::{StorageBackend, StorageConfig},
+    users::{
+        model::{User, UpsertUser},
+        service::{BatchOutcome, UserStore}
+    }
+};
+
+pub mod cache;
+pub mod health;
+pub mod memory;
+pub mod postgres;
+pub mod snapshot;
+pub mod table;
+pub mod wal;
+
+// - - - - - - - - - - - [REPOSITORY] - - - - - - - - - - -
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepositoryError {
+    // Another user has the email, as compared by the backend
+    #[error("a user with this email already exists")]
+    Duplicate,
+    #[error("storage backend failed: {0}")]
+    Backend(String),
+}
+
+// Where a listing continues, handed out with the page before. Only the backend that issued it can read it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageCursor(pub String);
+
+#[derive(Debug, Clone)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    // None after the last page
+    pub next: Option<PageCursor>,
+}
+
+impl UserPage {
+    // A page of users ordered by email, which the next page continues after its last email once it is full
+    pub fn by_email(users: Vec<User>, limit: usize) -> Self {
+        let next = users.last().filter(|_| users.len() >= limit).map(|user| PageCursor(user.email.clone()));
+        UserPage { users, next }
+    }
+}
+
+// Where users are kept. users::service times and traces every call, so implementations only store
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    // Fails with RepositoryError::Duplicate when the email is taken
+    async fn create(&self, request: UpsertUser) -> Result<User, RepositoryError>;
+
+    // One outcome per request, in order. An email repeated within the batch is a duplicate of its first
+    // occurrence. With all_or_nothing, nothing is stored unless every user can be created
+    async fn create_many(&self, requests: Vec<UpsertUser>, all_or_nothing: bool) -> Result<Vec<BatchOutcome>, RepositoryError>;
+
+    async fn get(&self, email: &str) -> Result<Option<User>, RepositoryError>;
+
+    // Replaces everything but the id and email, returning None when there is no such user
+    async fn update(&self, email: &str, request: UpsertUser) -> Result<Option<User>, RepositoryError>;
+
+    async fn delete(&self, email: &str) -> Result<Option<User>, RepositoryError>;
+
+    // At most limit users, from where the cursor of the previous page points or from the start. Following the
+    // cursors visits every user once; pages are ordered by email unless the backend documents another order
+    async fn list_page(&self, cursor: Option<&PageCursor>, limit: usize) -> Result<UserPage, RepositoryError>;
+
+    // Every user ordered by id
+    async fn list_all(&self) -> Result<Vec<User>, RepositoryError>;
+
+    // None when the backend would have to read every user to count them
+    async fn count(&self) -> Result<Option<usize>, RepositoryError>;
+
+    // Cheapest call that shows the backend can be reached
+    async fn ping(&self) -> Result<(), RepositoryError>;
+
+    // The form of an email under which the backend finds its user, so that two emails it treats as the same
+    // are kept as one entry by whatever sits in front of it
+    fn normalize_email(&self, email: &str) -> String {
+        email.to_string()
+    }
+
+    // Names of the schema migrations the backend still needs before it can be used
+    async fn pending_migrations(&self) -> Result<Vec<String>, RepositoryError> {
+        Ok(Vec::new())
+    }
+}
+
+// - - - - - - - - - - - [BACKENDS] - - - - - - - - - - -
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("the '{0}' storage backend keeps no state between processes")]
+    NotPersistent(&'static str),
+    #[error("snapshots are only taken of the memory backend")]
+    NotInMemory,
+    #[error("snapshot '{}': {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("snapshot '{}' is malformed: {source}", path.display())]
+    Malformed { path: PathBuf, source: serde_json::Error },
+    #[error("snapshot '{}' has version {version}, newer than the supported {supported}", path.display())]
+    UnsupportedVersion { path: PathBuf, version: u32, supported: u32 },
+    #[error("write-ahead log '{}': {source}", path.display())]
+    LogIo { path: PathBuf, source: io::Error },
+    #[error("write-ahead log '{}' has a malformed record at offset {offset}: {source}", path.display())]
+    MalformedRecord { path: PathBuf, offset: u64, source: serde_json::Error },
+    #[error("postgres: {0}")]
+    Postgres(#[from] sqlx::Error),
+    #[error("migration failed: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("table storage: {0}")]
+    Table(String),
+    #[error("redis: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+// The memory backend starts from its snapshot when one is configured, with the changes in its write-ahead log
+// since that snapshot applied on top
+pub async fn open(config: &StorageConfig) -> Result<UserStore, StorageError> {
+    match config.backend {
+        StorageBackend::Memory => {
+            let mut users = match &config.snapshot.path {
+                Some(path) => {
+                    let users = snapshot::read_snapshot(path)?;
+                    tracing::info!(path = %path.display(), users = users.len(), "snapshot loaded");
+                    users
+                }
+                None => HashMap::new(),
+            };
+
+            let Some(path) = &config.wal.path else {
+                return Ok(UserStore::with_users(users));
+            };
+            let changes = wal::replay(path, &mut users)?;
+            tracing::info!(path = %path.display(), changes, "write-ahead log replayed");
+            Ok(UserStore::from_memory(memory::MemoryRepository::new(users).with_journal(wal::Wal::open(path, &config.wal)?)))
+        }
+        StorageBackend::Postgres => {
+            let repository = postgres::PostgresRepository::connect(&config.postgres).await?;
+            cached(Arc::new(repository), config).await
+        }
+        StorageBackend::Table => {
+            let repository = table::TableRepository::connect(&config.table)?;
+            cached(Arc::new(repository), config).await
+        }
+    }
+}
+
+async fn cached(repository: Arc<dyn UserRepository>, config: &StorageConfig) -> Result<UserStore, StorageError> {
+    if !config.cache.enabled {
+        return Ok(UserStore::from_repository(repository));
+    }
+    let repository = cache::CachedRepository::open(repository, &config.cache).await?;
+    tracing::info!(backend = ?config.cache.backend, "user cache enabled");
+    Ok(UserStore::from_repository(Arc::new(repository)))
+}
+
+// Applies pending schema migrations and returns the names of those that were applied
+pub async fn migrate(config: &StorageConfig) -> Result<Vec<String>, StorageError> {
+    match config.backend {
+        StorageBackend::Memory => Ok(Vec::new()),
+        StorageBackend::Postgres => postgres::PostgresRepository::connect(&config.postgres).await?.migrate().await,
+        StorageBackend::Table => table::TableRepository::connect(&config.table)?.migrate().await,
+    }
+}
+
+// Persists anything still buffered in the backend, called once the server has stopped serving requests
+pub async fn flush(config: &StorageConfig, store: &UserStore) -> Result<(), StorageError> {
+    // Keeps the changes should the snapshot fail
+    if let Some(journal) = store.memory().and_then(|memory| memory.journal()) {
+        if let Err(error) = journal.sync().await {
+            tracing::error!(%error, "failed to sync journal");
+        }
+    }
+
+    match (config.backend, &config.snapshot.path) {
+        (StorageBackend::Memory, Some(path)) => snapshot::write_snapshot(path, store).await.map(|_| ()),
+        (StorageBackend::Memory, None) | (StorageBackend::Postgres | StorageBackend::Table, _) => Ok(()),
+    }
+}
+
+// Snapshots and logs hold every user, passwords included, so only the service's own account may read them
+pub(crate) fn private_file() -> OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+}
+
+// Offline commands such as import and export are pointless against a backend that forgets everything on exit
+pub fn ensure_persistent(config: &StorageConfig) -> Result<(), StorageError> {
+    match (config.backend, &config.snapshot.path) {
+        (StorageBackend::Memory, Some(_)) | (StorageBackend::Postgres | StorageBackend::Table, _) => Ok(()),
+        (StorageBackend::Memory, None) => Err(StorageError::NotPersistent("memory")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_backend_has_no_migrations() {
+        let applied = migrate(&StorageConfig::default()).await.unwrap();
+
+        assert!(applied.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_is_not_persistent() {
+        let result = ensure_persistent(&StorageConfig::default());
+
+        assert!(matches!(result, Err(StorageError::NotPersistent("memory"))));
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_with_snapshot_survives_reopening() {
+        let directory = std::env::temp_dir().join(format!("storage-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut config = StorageConfig::default();
+        config.snapshot.path = Some(directory.join("users.json"));
+        ensure_persistent(&config).unwrap();
+
+        let store = open(&config).await.unwrap();
+        let request = crate::users::model::UpsertUser {
+            email: "babu@dreamcafe.com".to_string(),
+            password: "very_bad_man".to_string(),
+            fullname: "Babu Bhatt".to_string(),
+            role: "restaurant_owner".to_string(),
+        };
+        crate::users::service::create_user(request, &store).await.unwrap();
+        flush(&config, &store).await.unwrap();
+
+        let reopened = open(&config).await.unwrap();
+        assert!(reopened.memory().unwrap().read().await.contains_key("babu@dreamcafe.com"));
+    }
+}
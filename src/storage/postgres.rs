@@ -0,0 +1,231 @@
+use std::{collections::HashSet, time::Duration};
+use async_trait::async_trait;
+use sqlx::{
+    migrate::Migrator,
+    postgres::{PgPool, PgPoolOptions, PgRow},
+    Row
+};
+use crate::{
+    config::PostgresConfig,
+    storage::{PageCursor, RepositoryError, StorageError, UserPage, UserRepository},
+    users::{model::{User, UpsertUser}, service::BatchOutcome}
+};
+
+// Embedded at compile time from migrations/, applied by the migrate command
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+// The unique index on lower(btrim(email)) from the first migration
+const NORMALIZED_EMAIL_KEY: &str = "users_normalized_email_key";
+
+// SQLSTATE of a query naming a table that does not exist
+const UNDEFINED_TABLE: &str = "42P01";
+
+const USER_COLUMNS: &str = "id, email, password, fullname, role";
+
+// Keeps users in PostgreSQL. Emails are compared trimmed and ignoring case, so "Jerry@Seinfeld.com " and
+// "jerry@seinfeld.com" are the same user, stored as first given
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    pub async fn connect(config: &PostgresConfig) -> Result<Self, StorageError> {
+        let url = config.url.as_ref().map(|url| url.expose()).unwrap_or_default();
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+            .connect(url)
+            .await?;
+        Ok(PostgresRepository { pool })
+    }
+
+    // Applies pending migrations and returns their names
+    pub async fn migrate(&self) -> Result<Vec<String>, StorageError> {
+        let pending = self.pending().await?;
+        MIGRATOR.run(&self.pool).await?;
+        Ok(pending)
+    }
+
+    // Only reads, since readiness probes ask for this. A database that was never migrated has no
+    // _sqlx_migrations table, which leaves every migration pending
+    async fn pending(&self) -> Result<Vec<String>, StorageError> {
+        let applied: HashSet<i64> = match sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success").fetch_all(&self.pool).await {
+            Ok(versions) => versions.into_iter().collect(),
+            Err(error) if error.as_database_error().and_then(|database| database.code()).as_deref() == Some(UNDEFINED_TABLE) => HashSet::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(MIGRATOR.iter()
+            .filter(|migration| !migration.migration_type.is_down_migration() && !applied.contains(&migration.version))
+            .map(|migration| format!("{:04}_{}", migration.version, migration.description.replace(' ', "_")))
+            .collect())
+    }
+}
+
+fn user_from_row(row: PgRow) -> Result<User, RepositoryError> {
+    let user = User {
+        id: row.try_get("id").map_err(backend_error)?,
+        email: row.try_get("email").map_err(backend_error)?,
+        password: row.try_get("password").map_err(backend_error)?,
+        fullname: row.try_get("fullname").map_err(backend_error)?,
+        role: row.try_get("role").map_err(backend_error)?,
+    };
+    Ok(user)
+}
+
+fn backend_error(error: sqlx::Error) -> RepositoryError {
+    match error.as_database_error() {
+        Some(database) if database.is_unique_violation() && database.constraint() == Some(NORMALIZED_EMAIL_KEY) => RepositoryError::Duplicate,
+        _ => RepositoryError::Backend(error.to_string()),
+    }
+}
+
+#[async_trait]
+impl UserRepository for PostgresRepository {
+    async fn create(&self, request: UpsertUser) -> Result<User, RepositoryError> {
+        let row = sqlx::query(&format!(
+            "INSERT INTO users (email, password, fullname, role) VALUES ($1, $2, $3, $4) RETURNING {}", USER_COLUMNS
+        ))
+            .bind(request.email)
+            .bind(request.password)
+            .bind(request.fullname)
+            .bind(request.role)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        user_from_row(row)
+    }
+
+    // Runs in one transaction, rolled back unless every user is created when all_or_nothing
+    async fn create_many(&self, requests: Vec<UpsertUser>, all_or_nothing: bool) -> Result<Vec<BatchOutcome>, RepositoryError> {
+        let mut transaction = self.pool.begin().await.map_err(backend_error)?;
+        let insert = format!(
+            "INSERT INTO users (email, password, fullname, role) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT ((lower(btrim(email)))) DO NOTHING RETURNING {}", USER_COLUMNS
+        );
+
+        let mut outcomes = Vec::with_capacity(requests.len());
+        for request in requests {
+            let row = sqlx::query(&insert)
+                .bind(request.email)
+                .bind(request.password)
+                .bind(request.fullname)
+                .bind(request.role)
+                .fetch_optional(&mut *transaction)
+                .await
+                .map_err(backend_error)?;
+            outcomes.push(match row {
+                Some(row) => BatchOutcome::Created(user_from_row(row)?),
+                None => BatchOutcome::Duplicate,
+            });
+        }
+
+        if all_or_nothing && outcomes.iter().any(|outcome| matches!(outcome, BatchOutcome::Duplicate)) {
+            transaction.rollback().await.map_err(backend_error)?;
+            return Ok(outcomes.into_iter()
+                .map(|outcome| match outcome {
+                    BatchOutcome::Created(_) => BatchOutcome::Skipped,
+                    outcome => outcome,
+                })
+                .collect());
+        }
+        transaction.commit().await.map_err(backend_error)?;
+        Ok(outcomes)
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        sqlx::query(&format!("SELECT {} FROM users WHERE lower(btrim(email)) = lower(btrim($1))", USER_COLUMNS))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(backend_error)?
+            .map(user_from_row)
+            .transpose()
+    }
+
+    // The row is locked from the moment it is found until the transaction commits, so a concurrent update or
+    // delete waits rather than interleaving
+    async fn update(&self, email: &str, request: UpsertUser) -> Result<Option<User>, RepositoryError> {
+        let mut transaction = self.pool.begin().await.map_err(backend_error)?;
+
+        let id: Option<i32> = sqlx::query_scalar("SELECT id FROM users WHERE lower(btrim(email)) = lower(btrim($1)) FOR UPDATE")
+            .bind(email)
+            .fetch_optional(&mut *transaction)
+            .await
+            .map_err(backend_error)?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+
+        let row = sqlx::query(&format!(
+            "UPDATE users SET password = $2, fullname = $3, role = $4 WHERE id = $1 RETURNING {}", USER_COLUMNS
+        ))
+            .bind(id)
+            .bind(request.password)
+            .bind(request.fullname)
+            .bind(request.role)
+            .fetch_one(&mut *transaction)
+            .await
+            .map_err(backend_error)?;
+        transaction.commit().await.map_err(backend_error)?;
+        user_from_row(row).map(Some)
+    }
+
+    async fn delete(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        sqlx::query(&format!("DELETE FROM users WHERE lower(btrim(email)) = lower(btrim($1)) RETURNING {}", USER_COLUMNS))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(backend_error)?
+            .map(user_from_row)
+            .transpose()
+    }
+
+    // Compares bytes rather than using the database collation, so pages are ordered as in the memory backend
+    async fn list_page(&self, cursor: Option<&PageCursor>, limit: usize) -> Result<UserPage, RepositoryError> {
+        let users = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE $1::text IS NULL OR email COLLATE \"C\" > $1 ORDER BY email COLLATE \"C\" LIMIT $2", USER_COLUMNS
+        ))
+            .bind(cursor.map(|PageCursor(after)| after.as_str()))
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(backend_error)?
+            .into_iter()
+            .map(user_from_row)
+            .collect::<Result<Vec<User>, RepositoryError>>()?;
+        Ok(UserPage::by_email(users, limit))
+    }
+
+    async fn list_all(&self) -> Result<Vec<User>, RepositoryError> {
+        sqlx::query(&format!("SELECT {} FROM users ORDER BY id", USER_COLUMNS))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(backend_error)?
+            .into_iter()
+            .map(user_from_row)
+            .collect()
+    }
+
+    async fn count(&self) -> Result<Option<usize>, RepositoryError> {
+        let count: i64 = sqlx::query_scalar("SELECT count(*) FROM users")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(Some(count as usize))
+    }
+
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await.map_err(backend_error)?;
+        Ok(())
+    }
+
+    // As lower(btrim(email)) in the queries above, which trims spaces only
+    fn normalize_email(&self, email: &str) -> String {
+        email.trim_matches(' ').to_lowercase()
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<String>, RepositoryError> {
+        self.pending().await.map_err(|error| RepositoryError::Backend(error.to_string()))
+    }
+}
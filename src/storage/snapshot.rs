@@ -0,0 +1,255 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::Duration
+};
+use serde_derive::{Serialize, Deserialize};
+use tokio::{
+    sync::{oneshot, Mutex},
+    task::JoinHandle
+};
+use crate::{
+    config::SnapshotConfig,
+    storage::{private_file, StorageError},
+    users::{model::User, service::UserStore}
+};
+
+// Bumped on a change to the file layout or to User that older snapshots cannot be read as. A User field added
+// with a serde default needs no bump, since snapshots without it still deserialize
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// Snapshots are written one at a time, so a periodic snapshot cannot finish after, and replace, the one taken
+// on shutdown. The file writer holds the guard itself, so a caller that is cancelled midway still keeps the
+// next snapshot waiting until its file is in place
+static WRITES: LazyLock<Arc<Mutex<()>>> = LazyLock::new(|| Arc::new(Mutex::new(())));
+
+#[derive(Serialize)]
+struct SnapshotOut<'a> {
+    version: u32,
+    users: Vec<&'a User>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct SnapshotIn {
+    users: Vec<User>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub path: PathBuf,
+    pub version: u32,
+    pub users: usize,
+}
+
+// Reads the users saved at path, or none when nothing has been saved there yet
+pub fn read_snapshot(path: &Path) -> Result<HashMap<String, User>, StorageError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(source) => return Err(StorageError::Io { path: path.to_path_buf(), source }),
+    };
+    let malformed = |source| StorageError::Malformed { path: path.to_path_buf(), source };
+
+    let SnapshotVersion { version } = serde_json::from_slice(&bytes).map_err(malformed)?;
+    if version > SNAPSHOT_VERSION {
+        return Err(StorageError::UnsupportedVersion { path: path.to_path_buf(), version, supported: SNAPSHOT_VERSION });
+    }
+
+    let SnapshotIn { users } = serde_json::from_slice(&bytes).map_err(malformed)?;
+    Ok(users.into_iter().map(|user| (user.email.clone(), user)).collect())
+}
+
+// Saves every user to path. The snapshot is written to a temporary file in the same directory and renamed over
+// the previous one, so a crash midway leaves the previous snapshot intact. Changes are only held off while the
+// store's journal is rotated and the users copied; the rotated journal is removed once the snapshot is saved
+pub async fn write_snapshot(path: &Path, store: &UserStore) -> Result<SnapshotInfo, StorageError> {
+    let Some(memory) = store.memory() else {
+        return Err(StorageError::NotInMemory);
+    };
+    let write = WRITES.clone().lock_owned().await;
+
+    let (mut users, rotated) = {
+        let acquired_map = memory.read().await;
+        let rotated = match memory.journal() {
+            Some(journal) => journal.rotate().await
+                .inspect_err(|error| tracing::warn!(%error, "failed to rotate journal for snapshot"))
+                .is_ok(),
+            None => false,
+        };
+        (acquired_map.values().cloned().collect::<Vec<User>>(), rotated)
+    };
+    users.sort_by_key(|user| user.id);
+    let info = SnapshotInfo { path: path.to_path_buf(), version: SNAPSHOT_VERSION, users: users.len() };
+
+    let target = path.to_path_buf();
+    let save = move || {
+        let bytes = serde_json::to_vec(&SnapshotOut { version: SNAPSHOT_VERSION, users: users.iter().collect() })
+            .expect("users serialize to JSON");
+        (write, replace_file(&target, &bytes))
+    };
+    let (_write, written) = tokio::task::spawn_blocking(save).await.expect("snapshot writer does not panic");
+    written.map_err(|source| StorageError::Io { path: path.to_path_buf(), source })?;
+
+    // Replaying a journal that was not removed repeats changes the snapshot already holds, which leaves the
+    // users as they are
+    if let Some(journal) = memory.journal().filter(|_| rotated) {
+        if let Err(error) = journal.remove_rotated().await {
+            tracing::warn!(%error, "failed to remove rotated journal after snapshot");
+        }
+    }
+
+    tracing::info!(path = %info.path.display(), users = info.users, "snapshot written");
+    Ok(info)
+}
+
+fn replace_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = private_file().write(true).create(true).truncate(true).open(&temporary)?;
+    file.write_all(bytes)?;
+    // The contents must be durable before the rename makes them the snapshot
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temporary, path)?;
+
+    // Persists the rename itself. Directories cannot be opened for syncing on every platform
+    #[cfg(unix)]
+    if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        File::open(directory)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Task saving the store on an interval, see spawn_periodic
+pub struct PeriodicSnapshots {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl PeriodicSnapshots {
+    // Lets a snapshot that is being written finish, then ends the task
+    pub async fn stop(self) {
+        let _ = self.stop.send(());
+        if let Err(error) = self.task.await {
+            tracing::error!(%error, "periodic snapshot task failed");
+        }
+    }
+}
+
+// Saves the store every interval_secs until stopped. A failed snapshot is logged and retried at the next interval
+pub fn spawn_periodic(config: &SnapshotConfig, store: &UserStore) -> Option<PeriodicSnapshots> {
+    let path = config.path.clone()?;
+    if config.interval_secs == 0 {
+        return None;
+    }
+
+    let period = Duration::from_secs(config.interval_secs);
+    let store = store.clone();
+    let (stop, mut stopped) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            tokio::select! {
+                biased;
+                _ = interval.tick() => {}
+                _ = &mut stopped => break,
+            }
+            if let Err(error) = write_snapshot(&path, &store).await {
+                tracing::error!(%error, "periodic snapshot failed");
+            }
+        }
+    });
+    Some(PeriodicSnapshots { stop, task })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::users::{model::UpsertUser, service::create_user};
+
+    fn snapshot_path(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("snapshot-tests-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        directory.join("users.json")
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let path = snapshot_path("round-trip");
+        let store = UserStore::new();
+        for email in ["jerry@seinfeld.com", "kramer@kramerica.com"] {
+            let request = UpsertUser {
+                email: email.to_string(),
+                password: "serenity_now".to_string(),
+                fullname: "Resident of 5A".to_string(),
+                role: "neighbor".to_string(),
+            };
+            create_user(request, &store).await.unwrap();
+        }
+
+        let info = write_snapshot(&path, &store).await.unwrap();
+        let users = read_snapshot(&path).unwrap();
+
+        assert_eq!(info.users, 2);
+        assert_eq!(users["kramer@kramerica.com"].id, 2);
+        assert!(!path.with_extension("json.tmp").exists());
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_waits_for_periodic_snapshot() {
+        let path = snapshot_path("periodic");
+        let _ = fs::remove_file(&path);
+        let config = SnapshotConfig { path: Some(path.clone()), interval_secs: 60 };
+
+        let snapshots = spawn_periodic(&config, &UserStore::new()).unwrap();
+        // Lets the task start its interval before time moves
+        tokio::task::yield_now().await;
+        tokio::time::advance(Duration::from_secs(61)).await;
+        snapshots.stop().await;
+
+        assert!(path.exists());
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn test_missing_snapshot_is_empty() {
+        let users = read_snapshot(&snapshot_path("missing").with_file_name("never-written.json")).unwrap();
+
+        assert!(users.is_empty());
+    }
+
+    #[test]
+    fn test_newer_version_is_refused() {
+        let path = snapshot_path("newer");
+        fs::write(&path, r#"{"version": 99, "users": [{"email": "bizarro@jerry.com", "shape": "square"}]}"#).unwrap();
+
+        let result = read_snapshot(&path);
+
+        assert!(matches!(result, Err(StorageError::UnsupportedVersion { version: 99, .. })));
+    }
+
+    #[test]
+    fn test_unknown_fields_of_version_1_are_ignored() {
+        let path = snapshot_path("unknown-fields");
+        let snapshot = r#"{"version": 1, "saved_by": "newman", "users": [
+            {"id": 1, "email": "newman@usps.com", "password": "hello_jerry", "fullname": "Newman", "role": "mailman", "route": 7}
+        ]}"#;
+        fs::write(&path, snapshot).unwrap();
+
+        let users = read_snapshot(&path).unwrap();
+
+        assert_eq!(users["newman@usps.com"].fullname, "Newman");
+    }
+}
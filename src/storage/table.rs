@@ -0,0 +1,530 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime}
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{header::HeaderMap, Method, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde_derive::{Serialize, Deserialize};
+use sha2::Sha256;
+use crate::{
+    config::TableConfig,
+    storage::{PageCursor, RepositoryError, StorageError, UserPage, UserRepository},
+    users::{model::{User, UpsertUser}, service::BatchOutcome}
+};
+
+const API_VERSION: &str = "2019-02-02";
+
+// The most entities the service answers a query with
+const MAX_PAGE_SIZE: usize = 1000;
+
+// The next id to hand out is kept next to the users, in a partition no email domain can have
+const META_PARTITION: &str = "_meta";
+const NEXT_ID_ROW: &str = "next_id";
+
+// Key characters stay readable in the URL, the rest is escaped
+const KEY: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~').remove(b'@');
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct UserEntity {
+    partition_key: String,
+    row_key: String,
+    id: i32,
+    email: String,
+    password: String,
+    fullname: String,
+    role: String,
+}
+
+impl UserEntity {
+    fn new(partition_key: String, user: User) -> Self {
+        UserEntity {
+            partition_key,
+            row_key: user.email.clone(),
+            id: user.id,
+            email: user.email,
+            password: user.password,
+            fullname: user.fullname,
+            role: user.role,
+        }
+    }
+
+    fn into_user(self) -> User {
+        User { id: self.id, email: self.email, password: self.password, fullname: self.fullname, role: self.role }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CounterEntity {
+    partition_key: String,
+    row_key: String,
+    value: i32,
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    value: Vec<T>,
+}
+
+// Partition and row key the service continues a query from
+type Continuation = (String, String);
+
+// Partition and row key of a user: the domain of the email, lowercased so a domain's users share a partition,
+// and the email as given. None for an email no user can have, such as one with characters keys may not contain
+fn keys(email: &str) -> Option<(String, String)> {
+    let (_, domain) = email.rsplit_once('@')?;
+    if domain.is_empty() || email.chars().any(|c| matches!(c, '/' | '\\' | '#' | '?') || c.is_control()) {
+        return None;
+    }
+    Some((domain.to_ascii_lowercase(), email.to_string()))
+}
+
+// A string literal of an OData filter or key
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+// Signs a request for the SharedKeyLite scheme of the Table service. The resource is the path as sent, which
+// for Azurite and other path-style endpoints begins with the account name a second time
+fn sign(key: &[u8], account: &str, date: &str, path: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}\n/{}{}", date, account, path).as_bytes());
+    STANDARD.encode(mac.finalize().into_bytes())
+}
+
+// Keeps users in an Azure Storage table, or in Azurite locally, through the Table service REST API. The domain of
+// an email is its partition and the email its row. Updates and deletes only apply to the entity as it was read,
+// per its ETag, and are tried again when another writer got there first
+pub struct TableRepository {
+    client: reqwest::Client,
+    endpoint: String,
+    account: String,
+    key: Vec<u8>,
+    table: String,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl TableRepository {
+    pub fn connect(config: &TableConfig) -> Result<Self, StorageError> {
+        let key = config.key.as_ref().map(|key| key.expose()).unwrap_or_default();
+        let key = STANDARD.decode(key).map_err(|error| StorageError::Table(format!("storage.table.key is not base64: {}", error)))?;
+        let endpoint = config.endpoint.clone().unwrap_or_default().trim_end_matches('/').to_string();
+        Url::parse(&endpoint).map_err(|error| StorageError::Table(format!("storage.table.endpoint '{}': {}", endpoint, error)))?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|error| StorageError::Table(error.to_string()))?;
+
+        Ok(TableRepository {
+            client,
+            endpoint,
+            account: config.account.clone().unwrap_or_default(),
+            key,
+            table: config.table.clone(),
+            max_retries: config.max_retries,
+            retry_delay: Duration::from_millis(config.retry_delay_ms),
+        })
+    }
+
+    // Creates the table unless it exists, returning the name of the migration when it did not
+    pub async fn migrate(&self) -> Result<Vec<String>, StorageError> {
+        let body = serde_json::json!({ "TableName": self.table });
+        let response = self.send(Method::POST, "Tables", &[], None, Some(body)).await.map_err(table_error)?;
+        match response.status() {
+            StatusCode::CREATED | StatusCode::NO_CONTENT => Ok(vec![self.migration()]),
+            StatusCode::CONFLICT => Ok(Vec::new()),
+            _ => Err(table_error(unexpected(response).await)),
+        }
+    }
+
+    fn migration(&self) -> String {
+        format!("create_table_{}", self.table)
+    }
+
+    // Sends a request, trying again while the service is busy or failing. Requests that may have been applied
+    // are only tried again when repeating them does no harm, which is the case for reads and conditional updates
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        if_match: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> Result<Response, RepositoryError> {
+        let mut url = Url::parse(&format!("{}/{}", self.endpoint, path)).map_err(|error| RepositoryError::Backend(error.to_string()))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        let repeatable = method == Method::GET || method == Method::PUT;
+
+        let mut attempt = 0;
+        loop {
+            let date = httpdate::fmt_http_date(SystemTime::now());
+            let signature = sign(&self.key, &self.account, &date, url.path());
+            let mut request = self.client.request(method.clone(), url.clone())
+                .header("x-ms-date", &date)
+                .header("x-ms-version", API_VERSION)
+                .header("Authorization", format!("SharedKeyLite {}:{}", self.account, signature))
+                .header("Accept", "application/json;odata=nometadata")
+                .header("DataServiceVersion", "3.0;NetFx")
+                .header("MaxDataServiceVersion", "3.0;NetFx");
+            if let Some(etag) = if_match {
+                request = request.header("If-Match", etag);
+            }
+            if let Some(body) = &body {
+                request = request
+                    .header("Content-Type", "application/json")
+                    .header("Prefer", "return-no-content")
+                    .body(body.to_string());
+            }
+
+            let retry = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let busy = status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE;
+                    let failed = matches!(status, StatusCode::REQUEST_TIMEOUT | StatusCode::INTERNAL_SERVER_ERROR | StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT);
+                    if !(busy || repeatable && failed) || attempt >= self.max_retries {
+                        return Ok(response);
+                    }
+                    status.to_string()
+                }
+                Err(error) => {
+                    if !(error.is_connect() || repeatable && error.is_timeout()) || attempt >= self.max_retries {
+                        return Err(RepositoryError::Backend(format!("table storage request failed: {}", error)));
+                    }
+                    error.to_string()
+                }
+            };
+
+            let delay = self.retry_delay * 2u32.saturating_pow(attempt);
+            tracing::warn!(%method, path = url.path(), attempt, reason = %retry, "retrying table storage request");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn entity_path(&self, partition_key: &str, row_key: &str) -> String {
+        format!(
+            "{}(PartitionKey='{}',RowKey='{}')",
+            self.table,
+            utf8_percent_encode(&partition_key.replace('\'', "''"), KEY),
+            utf8_percent_encode(&row_key.replace('\'', "''"), KEY)
+        )
+    }
+
+    // The entity with its ETag
+    async fn get_entity<T: DeserializeOwned>(&self, partition_key: &str, row_key: &str) -> Result<Option<(T, String)>, RepositoryError> {
+        let response = self.send(Method::GET, &self.entity_path(partition_key, row_key), &[], None, None).await?;
+        match response.status() {
+            StatusCode::OK => {
+                let etag = etag(response.headers())?;
+                let entity = response.json().await.map_err(|error| RepositoryError::Backend(error.to_string()))?;
+                Ok(Some((entity, etag)))
+            }
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(unexpected(response).await),
+        }
+    }
+
+    // Fails with RepositoryError::Duplicate when an entity has the keys, and returns the ETag otherwise
+    async fn insert<T: serde::Serialize>(&self, entity: &T) -> Result<String, RepositoryError> {
+        let body = serde_json::to_value(entity).map_err(|error| RepositoryError::Backend(error.to_string()))?;
+        let response = self.send(Method::POST, &self.table, &[], None, Some(body)).await?;
+        match response.status() {
+            StatusCode::CREATED | StatusCode::NO_CONTENT => etag(response.headers()),
+            StatusCode::CONFLICT => Err(RepositoryError::Duplicate),
+            _ => Err(unexpected(response).await),
+        }
+    }
+
+    // Replaces the entity if it still has the ETag. False when it has changed since
+    async fn replace<T: serde::Serialize>(&self, partition_key: &str, row_key: &str, etag: &str, entity: &T) -> Result<Option<bool>, RepositoryError> {
+        let body = serde_json::to_value(entity).map_err(|error| RepositoryError::Backend(error.to_string()))?;
+        let response = self.send(Method::PUT, &self.entity_path(partition_key, row_key), &[], Some(etag), Some(body)).await?;
+        conditional(response).await
+    }
+
+    // Deletes the entity if it still has the ETag. False when it has changed since, None when it is gone
+    async fn remove(&self, partition_key: &str, row_key: &str, etag: &str) -> Result<Option<bool>, RepositoryError> {
+        let response = self.send(Method::DELETE, &self.entity_path(partition_key, row_key), &[], Some(etag), None).await?;
+        conditional(response).await
+    }
+
+    // Takes the next id from the counter, racing other writers for it through its ETag
+    async fn next_id(&self) -> Result<i32, RepositoryError> {
+        loop {
+            match self.get_entity::<CounterEntity>(META_PARTITION, NEXT_ID_ROW).await? {
+                None => {
+                    let counter = CounterEntity { partition_key: META_PARTITION.to_string(), row_key: NEXT_ID_ROW.to_string(), value: 2 };
+                    match self.insert(&counter).await {
+                        Ok(_) => return Ok(1),
+                        Err(RepositoryError::Duplicate) => continue,
+                        Err(error) => return Err(error),
+                    }
+                }
+                Some((mut counter, etag)) => {
+                    let id = counter.value;
+                    counter.value += 1;
+                    if self.replace(META_PARTITION, NEXT_ID_ROW, &etag, &counter).await? == Some(true) {
+                        return Ok(id);
+                    }
+                }
+            }
+        }
+    }
+
+    // One page of users as the service hands them out, with the continuation token of the next page
+    async fn query_users(&self, continuation: Option<&Continuation>, top: Option<usize>) -> Result<(Vec<User>, Option<Continuation>), RepositoryError> {
+        let filter = format!("PartitionKey ne {}", literal(META_PARTITION));
+        let top = top.map(|top| top.min(MAX_PAGE_SIZE).to_string());
+        let mut query = vec![("$filter", filter.as_str())];
+        if let Some(top) = &top {
+            query.push(("$top", top));
+        }
+        if let Some((partition_key, row_key)) = continuation {
+            query.push(("NextPartitionKey", partition_key));
+            query.push(("NextRowKey", row_key));
+        }
+
+        let response = self.send(Method::GET, &format!("{}()", self.table), &query, None, None).await?;
+        if response.status() != StatusCode::OK {
+            return Err(unexpected(response).await);
+        }
+        let next_partition_key = header(response.headers(), "x-ms-continuation-NextPartitionKey");
+        let next_row_key = header(response.headers(), "x-ms-continuation-NextRowKey");
+        let page: Page<UserEntity> = response.json().await.map_err(|error| RepositoryError::Backend(error.to_string()))?;
+
+        let next = next_partition_key.map(|partition_key| (partition_key, next_row_key.unwrap_or_default()));
+        Ok((page.value.into_iter().map(UserEntity::into_user).collect(), next))
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+fn etag(headers: &HeaderMap) -> Result<String, RepositoryError> {
+    header(headers, "ETag").ok_or_else(|| RepositoryError::Backend("table storage sent no ETag".to_string()))
+}
+
+async fn conditional(response: Response) -> Result<Option<bool>, RepositoryError> {
+    match response.status() {
+        StatusCode::NO_CONTENT | StatusCode::OK => Ok(Some(true)),
+        StatusCode::PRECONDITION_FAILED => Ok(Some(false)),
+        StatusCode::NOT_FOUND => Ok(None),
+        _ => Err(unexpected(response).await),
+    }
+}
+
+// Describes an answer the caller did not expect by its status and the error code in its body
+async fn unexpected(response: Response) -> RepositoryError {
+    let status = response.status();
+    let body: serde_json::Value = response.json().await.unwrap_or_default();
+    let code = body["odata.error"]["code"].as_str().unwrap_or("no error code");
+    RepositoryError::Backend(format!("table storage answered {}: {}", status, code))
+}
+
+fn table_error(error: RepositoryError) -> StorageError {
+    StorageError::Table(error.to_string())
+}
+
+#[async_trait]
+impl UserRepository for TableRepository {
+    // An id is taken before the insert, so a duplicate leaves a gap in the ids
+    async fn create(&self, request: UpsertUser) -> Result<User, RepositoryError> {
+        let (partition_key, _) = keys(&request.email)
+            .ok_or_else(|| RepositoryError::Backend(format!("'{}' cannot be a table key", request.email)))?;
+        let user = User {
+            id: self.next_id().await?,
+            email: request.email,
+            password: request.password,
+            fullname: request.fullname,
+            role: request.role,
+        };
+        self.insert(&UserEntity::new(partition_key, user.clone())).await?;
+        Ok(user)
+    }
+
+    // Transactions of the Table service cannot span partitions, so an all-or-nothing batch checks every email
+    // up front and deletes the users it created should another writer take one of the emails in the meantime
+    async fn create_many(&self, requests: Vec<UpsertUser>, all_or_nothing: bool) -> Result<Vec<BatchOutcome>, RepositoryError> {
+        if !all_or_nothing {
+            let mut outcomes = Vec::with_capacity(requests.len());
+            for request in requests {
+                outcomes.push(match self.create(request).await {
+                    Ok(user) => BatchOutcome::Created(user),
+                    Err(RepositoryError::Duplicate) => BatchOutcome::Duplicate,
+                    Err(error) => return Err(error),
+                });
+            }
+            return Ok(outcomes);
+        }
+
+        let mut seen = HashSet::new();
+        let mut duplicates = Vec::with_capacity(requests.len());
+        for request in &requests {
+            duplicates.push(!seen.insert(request.email.clone()) || self.get(&request.email).await?.is_some());
+        }
+        if duplicates.contains(&true) {
+            return Ok(duplicates.into_iter()
+                .map(|duplicate| if duplicate { BatchOutcome::Duplicate } else { BatchOutcome::Skipped })
+                .collect());
+        }
+
+        let count = requests.len();
+        let mut created: Vec<User> = Vec::with_capacity(count);
+        for request in requests {
+            match self.create(request).await {
+                Ok(user) => created.push(user),
+                Err(error) => {
+                    for user in &created {
+                        if let Err(error) = self.delete(&user.email).await {
+                            tracing::error!(%error, id = user.id, "failed to undo user of an all-or-nothing batch");
+                        }
+                    }
+                    if !matches!(error, RepositoryError::Duplicate) {
+                        return Err(error);
+                    }
+                    let mut outcomes = vec![BatchOutcome::Skipped; count];
+                    outcomes[created.len()] = BatchOutcome::Duplicate;
+                    return Ok(outcomes);
+                }
+            }
+        }
+        Ok(created.into_iter().map(BatchOutcome::Created).collect())
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        let Some((partition_key, row_key)) = keys(email) else {
+            return Ok(None);
+        };
+        let entity = self.get_entity::<UserEntity>(&partition_key, &row_key).await?;
+        Ok(entity.map(|(entity, _)| entity.into_user()))
+    }
+
+    async fn update(&self, email: &str, request: UpsertUser) -> Result<Option<User>, RepositoryError> {
+        let Some((partition_key, row_key)) = keys(email) else {
+            return Ok(None);
+        };
+
+        loop {
+            let Some((entity, etag)) = self.get_entity::<UserEntity>(&partition_key, &row_key).await? else {
+                return Ok(None);
+            };
+            let updated_user = User {
+                id: entity.id,
+                email: entity.email,
+                password: request.password.clone(),
+                fullname: request.fullname.clone(),
+                role: request.role.clone(),
+            };
+            match self.replace(&partition_key, &row_key, &etag, &UserEntity::new(partition_key.clone(), updated_user.clone())).await? {
+                Some(true) => return Ok(Some(updated_user)),
+                Some(false) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    async fn delete(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        let Some((partition_key, row_key)) = keys(email) else {
+            return Ok(None);
+        };
+
+        loop {
+            let Some((entity, etag)) = self.get_entity::<UserEntity>(&partition_key, &row_key).await? else {
+                return Ok(None);
+            };
+            match self.remove(&partition_key, &row_key, &etag).await? {
+                Some(true) => return Ok(Some(entity.into_user())),
+                Some(false) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    // Pages are in the order the service keeps rows, by email domain and then by email, since ordering by email
+    // alone would read the whole table for every page. Each page continues from the service's own continuation
+    // token and may hold fewer than limit users even when more follow
+    async fn list_page(&self, cursor: Option<&PageCursor>, limit: usize) -> Result<UserPage, RepositoryError> {
+        if limit == 0 {
+            return Ok(UserPage { users: Vec::new(), next: cursor.cloned() });
+        }
+        let continuation = cursor
+            .map(|PageCursor(cursor)| serde_json::from_str::<Continuation>(cursor))
+            .transpose()
+            .map_err(|_| RepositoryError::Backend("malformed table page cursor".to_string()))?;
+
+        let (users, next) = self.query_users(continuation.as_ref(), Some(limit)).await?;
+        let next = next.map(|next| PageCursor(serde_json::to_string(&next).expect("keys serialize to JSON")));
+        Ok(UserPage { users, next })
+    }
+
+    async fn list_all(&self) -> Result<Vec<User>, RepositoryError> {
+        let mut users = Vec::new();
+        let mut continuation = None;
+        loop {
+            let (page, next) = self.query_users(continuation.as_ref(), None).await?;
+            users.extend(page);
+            continuation = next;
+            if continuation.is_none() {
+                break;
+            }
+        }
+        users.sort_by_key(|user| user.id);
+        Ok(users)
+    }
+
+    // The service keeps no count, and metrics scrapes must not read the whole table
+    async fn count(&self) -> Result<Option<usize>, RepositoryError> {
+        Ok(None)
+    }
+
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        self.pending_migrations().await.map(|_| ())
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<String>, RepositoryError> {
+        let response = self.send(Method::GET, &format!("Tables({})", literal(&self.table)), &[], None, None).await?;
+        match response.status() {
+            StatusCode::OK => Ok(Vec::new()),
+            StatusCode::NOT_FOUND => Ok(vec![self.migration()]),
+            _ => Err(unexpected(response).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_is_the_partition() {
+        assert_eq!(keys("Jerry@Seinfeld.COM"), Some(("seinfeld.com".to_string(), "Jerry@Seinfeld.COM".to_string())));
+        assert_eq!(keys("newman"), None);
+        assert_eq!(keys("newman@"), None);
+        assert_eq!(keys("newman/mailman@usps.gov"), None);
+    }
+
+    #[test]
+    fn test_quotes_are_doubled() {
+        assert_eq!(literal("o'brien@dublin.ie"), "'o''brien@dublin.ie'");
+    }
+
+    // Azurite's published account key, with the signature computed apart from this code by Python's hmac module over
+    // "<x-ms-date>\n/<account><path>", the string Shared Key Lite signs for the Table service. test_azurite_round_trip
+    // in tests/table_tests.rs checks the scheme against Azurite itself
+    #[test]
+    fn test_signature_covers_date_and_resource() {
+        let key = STANDARD.decode("Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==").unwrap();
+        let signature = sign(&key, "devstoreaccount1", "Fri, 16 Oct 2026 12:00:00 GMT", "/devstoreaccount1/users()");
+
+        assert_eq!(signature, "sX6r7kpOc5BMpLOAmBM7x6hFPZQgvpFYEvAN0+TqGcQ=");
+    }
+}
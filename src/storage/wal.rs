@@ -0,0 +1,448 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    iter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc
+    },
+    thread,
+    time::{Duration, Instant}
+};
+use async_trait::async_trait;
+use tokio::sync::oneshot;
+use crate::{
+    config::{FsyncPolicy, WalConfig},
+    storage::{memory::{Change, Journal}, private_file, StorageError},
+    users::model::User
+};
+
+// Each record is the length and CRC-32 of its payload, both little-endian u32, followed by the payload: one
+// Change as JSON. A crash midway through an append leaves a record that is short or fails its checksum
+const HEADER_LEN: usize = 8;
+
+// Append-only log of the changes made since the last snapshot. The file is only touched by a thread of its own,
+// so appends and fsyncs never block the runtime, and appends that queue up while one is being synced share the
+// next fsync
+pub struct Wal {
+    path: PathBuf,
+    requests: Sender<Request>,
+    // Set once a change could not be recorded, after which none are until a snapshot covers the log
+    failed: Arc<AtomicBool>,
+}
+
+type Reply = oneshot::Sender<Result<(), String>>;
+
+enum Request {
+    Append { records: Vec<u8>, done: Reply },
+    Sync { done: Reply },
+    Rotate { done: Reply },
+    RemoveRotated { done: Reply },
+}
+
+impl Wal {
+    // Opens the log at path for appending. Under the batched policy, the writer forces appends to disk every
+    // fsync_interval_ms for as long as the log is open
+    pub fn open(path: &Path, config: &WalConfig) -> Result<Arc<Wal>, StorageError> {
+        let io_error = |source| StorageError::LogIo { path: path.to_path_buf(), source };
+        let file = private_file().create(true).append(true).open(path).map_err(io_error)?;
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let writer = Writer {
+            path: path.to_path_buf(),
+            file,
+            fsync: config.fsync,
+            sync_interval: Duration::from_millis(config.fsync_interval_ms),
+            sync_at: None,
+            dirty: false,
+            segment: 0,
+            failed_in: None,
+            failed: failed.clone(),
+        };
+        let (requests, received) = mpsc::channel();
+        thread::Builder::new()
+            .name("wal-writer".to_string())
+            .spawn(move || writer.run(received))
+            .map_err(io_error)?;
+
+        Ok(Arc::new(Wal { path: path.to_path_buf(), requests, failed }))
+    }
+
+    async fn request(&self, request: impl FnOnce(Reply) -> Request) -> Result<(), String> {
+        let (done, reply) = oneshot::channel();
+        let stopped = || format!("write-ahead log '{}': writer has stopped", self.path.display());
+        self.requests.send(request(done)).map_err(|_| stopped())?;
+        reply.await.map_err(|_| stopped())?
+    }
+}
+
+#[async_trait]
+impl Journal for Wal {
+    async fn record(&self, changes: &[Change]) -> Result<(), String> {
+        if self.failed.load(Ordering::Acquire) {
+            return Err(refused(&self.path));
+        }
+        let records = changes.iter().flat_map(encode).collect();
+        self.request(|done| Request::Append { records, done }).await
+    }
+
+    async fn sync(&self) -> Result<(), String> {
+        self.request(|done| Request::Sync { done }).await
+    }
+
+    async fn rotate(&self) -> Result<(), String> {
+        self.request(|done| Request::Rotate { done }).await
+    }
+
+    async fn remove_rotated(&self) -> Result<(), String> {
+        self.request(|done| Request::RemoveRotated { done }).await
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.failed.load(Ordering::Acquire) {
+            Err(refused(&self.path))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn refused(path: &Path) -> String {
+    format!("write-ahead log '{}' has failed, changes are refused until the next snapshot", path.display())
+}
+
+// The log's previous segment, kept from a rotation until the snapshot that covers it is saved
+pub fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+// - - - - - - - - - - - [WRITER] - - - - - - - - - - -
+
+struct Writer {
+    path: PathBuf,
+    file: File,
+    fsync: FsyncPolicy,
+    sync_interval: Duration,
+    // When appends not yet forced to disk are due to be, under the batched policy
+    sync_at: Option<Instant>,
+    dirty: bool,
+    // Counts rotations, so a snapshot only clears a failure from a segment it covers
+    segment: u64,
+    failed_in: Option<u64>,
+    failed: Arc<AtomicBool>,
+}
+
+impl Writer {
+    // Handles requests until the log is dropped
+    fn run(mut self, requests: Receiver<Request>) {
+        loop {
+            let first = match self.sync_at {
+                Some(sync_at) => match requests.recv_timeout(sync_at.saturating_duration_since(Instant::now())) {
+                    Ok(request) => request,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Err(error) = self.sync() {
+                            tracing::error!(%error, "failed to sync write-ahead log");
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+                None => match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                },
+            };
+
+            let mut appended = Vec::new();
+            for request in iter::once(first).chain(requests.try_iter()) {
+                match request {
+                    Request::Append { records, done } => match self.append(&records) {
+                        Ok(()) => appended.push(done),
+                        Err(error) => {
+                            let _ = done.send(Err(error));
+                        }
+                    },
+                    Request::Sync { done } => {
+                        self.commit(&mut appended);
+                        let _ = done.send(self.sync());
+                    }
+                    Request::Rotate { done } => {
+                        self.commit(&mut appended);
+                        let _ = done.send(self.rotate());
+                    }
+                    Request::RemoveRotated { done } => {
+                        self.commit(&mut appended);
+                        let _ = done.send(self.remove_rotated());
+                    }
+                }
+            }
+            self.commit(&mut appended);
+        }
+
+        if self.fsync == FsyncPolicy::Batched {
+            if let Err(error) = self.sync() {
+                tracing::error!(%error, "failed to sync write-ahead log");
+            }
+        }
+    }
+
+    fn error(&self, error: io::Error) -> String {
+        format!("write-ahead log '{}': {}", self.path.display(), error)
+    }
+
+    fn fail(&mut self, error: io::Error) -> String {
+        self.failed_in.get_or_insert(self.segment);
+        self.failed.store(true, Ordering::Release);
+        self.error(error)
+    }
+
+    // A record that was refused may have been written in part, which would hide every later record from replay,
+    // so after a failure nothing is appended to the segment again
+    fn append(&mut self, records: &[u8]) -> Result<(), String> {
+        if self.failed_in.is_some() {
+            return Err(refused(&self.path));
+        }
+        self.file.write_all(records).map_err(|error| self.fail(error))?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    // Answers the appends made since the last commit, with one fsync for all of them under the always policy
+    fn commit(&mut self, appended: &mut Vec<Reply>) {
+        if appended.is_empty() {
+            return;
+        }
+        let result = match self.fsync {
+            FsyncPolicy::Always => self.sync(),
+            FsyncPolicy::Batched => {
+                self.sync_at.get_or_insert_with(|| Instant::now() + self.sync_interval);
+                Ok(())
+            }
+            FsyncPolicy::Os => Ok(()),
+        };
+        for done in appended.drain(..) {
+            let _ = done.send(result.clone());
+        }
+    }
+
+    // Forces every append so far to disk. Appends that were answered before a failed sync may be lost, so the
+    // log fails as if they had not been recorded
+    fn sync(&mut self) -> Result<(), String> {
+        self.sync_at = None;
+        if !self.dirty {
+            return Ok(());
+        }
+        self.dirty = false;
+        self.file.sync_data().map_err(|error| self.fail(error))
+    }
+
+    // Moves the segment aside and starts an empty one. A rotated segment left by a snapshot that failed still
+    // holds changes no snapshot covers, so this segment is added to its end instead
+    fn rotate(&mut self) -> Result<(), String> {
+        self.sync()?;
+        let rotated = rotated_path(&self.path);
+
+        if rotated.exists() {
+            let mut target = private_file().append(true).open(&rotated).map_err(|error| self.error(error))?;
+            let len = target.metadata().map_err(|error| self.error(error))?.len();
+            let copied = File::open(&self.path)
+                .and_then(|mut source| io::copy(&mut source, &mut target))
+                .and_then(|_| target.sync_data());
+            if let Err(error) = copied {
+                // A copy cut short would hide the next one from replay
+                let _ = target.set_len(len);
+                return Err(self.error(error));
+            }
+            self.file.set_len(0).and_then(|_| self.file.sync_all()).map_err(|error| self.fail(error))?;
+        } else {
+            fs::rename(&self.path, &rotated).map_err(|error| self.error(error))?;
+            match private_file().create(true).append(true).open(&self.path) {
+                Ok(file) => self.file = file,
+                Err(error) => {
+                    let _ = fs::rename(&rotated, &self.path);
+                    return Err(self.error(error));
+                }
+            }
+        }
+
+        self.segment += 1;
+        Ok(())
+    }
+
+    // Called once a snapshot covers every change up to the last rotation
+    fn remove_rotated(&mut self) -> Result<(), String> {
+        match fs::remove_file(rotated_path(&self.path)) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(self.error(error)),
+        }
+        if self.failed_in.is_some_and(|segment| segment < self.segment) {
+            self.failed_in = None;
+            self.failed.store(false, Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+// - - - - - - - - - - - [RECORDS] - - - - - - - - - - -
+
+fn encode(change: &Change) -> Vec<u8> {
+    let payload = serde_json::to_vec(change).expect("changes serialize to JSON");
+    let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+// Applies the changes recorded in the log at path, its rotated segment first, to users and returns how many
+// there were
+pub fn replay(path: &Path, users: &mut HashMap<String, User>) -> Result<usize, StorageError> {
+    Ok(replay_segment(&rotated_path(path), users)? + replay_segment(path, users)?)
+}
+
+// A segment ends at the first record that is short or fails its checksum, which is what a crash midway through
+// an append leaves behind; it is cut off there so that later appends are not hidden behind it
+fn replay_segment(path: &Path, users: &mut HashMap<String, User>) -> Result<usize, StorageError> {
+    let io_error = |source| StorageError::LogIo { path: path.to_path_buf(), source };
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(source) => return Err(io_error(source)),
+    };
+
+    let mut offset = 0;
+    let mut applied = 0;
+    while let Some(payload) = next_record(&bytes[offset..]) {
+        let change: Change = serde_json::from_slice(payload)
+            .map_err(|source| StorageError::MalformedRecord { path: path.to_path_buf(), offset: offset as u64, source })?;
+        change.apply(users);
+        offset += HEADER_LEN + payload.len();
+        applied += 1;
+    }
+
+    if offset < bytes.len() {
+        tracing::warn!(path = %path.display(), offset, discarded = bytes.len() - offset, "write-ahead log ends in an incomplete record, truncating");
+        let file = OpenOptions::new().write(true).open(path).map_err(io_error)?;
+        file.set_len(offset as u64).and_then(|_| file.sync_all()).map_err(io_error)?;
+    }
+    Ok(applied)
+}
+
+fn next_record(bytes: &[u8]) -> Option<&[u8]> {
+    let header = bytes.get(..HEADER_LEN)?;
+    let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+    let checksum = u32::from_le_bytes(header[4..].try_into().unwrap());
+    let payload = bytes.get(HEADER_LEN..HEADER_LEN.checked_add(len)?)?;
+    (crc32fast::hash(payload) == checksum).then_some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_path(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("wal-tests-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("users.wal");
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(rotated_path(&path));
+        path
+    }
+
+    fn user(id: i32, email: &str, role: &str) -> User {
+        User {
+            id,
+            email: email.to_string(),
+            password: "hello_newman".to_string(),
+            fullname: "Jerry Seinfeld".to_string(),
+            role: role.to_string(),
+        }
+    }
+
+    fn os_config() -> WalConfig {
+        WalConfig { fsync: FsyncPolicy::Os, ..WalConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn test_replay_applies_changes_in_order() {
+        let path = log_path("in-order");
+        let wal = Wal::open(&path, &os_config()).unwrap();
+        wal.record(&[Change::Create { user: user(1, "jerry@seinfeld.com", "comedian") }]).await.unwrap();
+        wal.record(&[
+            Change::Create { user: user(2, "george@costanza.com", "architect") },
+            Change::Update { user: user(1, "jerry@seinfeld.com", "headliner") },
+        ]).await.unwrap();
+        wal.record(&[Change::Delete { email: "george@costanza.com".to_string() }]).await.unwrap();
+
+        let mut users = HashMap::new();
+        let applied = replay(&path, &mut users).unwrap();
+
+        assert_eq!(applied, 4);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users["jerry@seinfeld.com"].role, "headliner");
+    }
+
+    #[test]
+    fn test_bad_checksum_ends_the_log() {
+        let path = log_path("bad-checksum");
+        let first = encode(&Change::Create { user: user(1, "jerry@seinfeld.com", "comedian") });
+        let mut second = encode(&Change::Create { user: user(2, "elaine@benes.com", "editor") });
+        let last = second.len() - 1;
+        second[last] ^= 0xff;
+        fs::write(&path, [first.clone(), second].concat()).unwrap();
+
+        let mut users = HashMap::new();
+        assert_eq!(replay(&path, &mut users).unwrap(), 1);
+
+        assert!(!users.contains_key("elaine@benes.com"));
+        assert_eq!(fs::read(&path).unwrap(), first);
+    }
+
+    #[test]
+    fn test_missing_log_is_empty() {
+        let mut users = HashMap::new();
+
+        assert_eq!(replay(&log_path("missing"), &mut users).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rotated_segment_is_replayed_until_removed() {
+        let path = log_path("rotate");
+        let wal = Wal::open(&path, &os_config()).unwrap();
+        wal.record(&[Change::Create { user: user(1, "jerry@seinfeld.com", "comedian") }]).await.unwrap();
+
+        wal.rotate().await.unwrap();
+        wal.record(&[Change::Create { user: user(2, "kramer@kramerica.com", "entrepreneur") }]).await.unwrap();
+
+        let mut users = HashMap::new();
+        assert_eq!(replay(&path, &mut users).unwrap(), 2);
+        assert_eq!(fs::metadata(&path).unwrap().len(), encode(&Change::Create { user: user(2, "kramer@kramerica.com", "entrepreneur") }).len() as u64);
+
+        wal.remove_rotated().await.unwrap();
+        let mut users = HashMap::new();
+        assert_eq!(replay(&path, &mut users).unwrap(), 1);
+        assert!(users.contains_key("kramer@kramerica.com"));
+    }
+
+    #[tokio::test]
+    async fn test_rotating_again_keeps_the_unsnapshotted_segment() {
+        let path = log_path("rotate-twice");
+        let wal = Wal::open(&path, &os_config()).unwrap();
+        wal.record(&[Change::Create { user: user(1, "jerry@seinfeld.com", "comedian") }]).await.unwrap();
+        wal.rotate().await.unwrap();
+        wal.record(&[Change::Create { user: user(2, "kramer@kramerica.com", "entrepreneur") }]).await.unwrap();
+
+        // As when the snapshot after the first rotation failed
+        wal.rotate().await.unwrap();
+
+        let mut users = HashMap::new();
+        assert_eq!(replay(&path, &mut users).unwrap(), 2);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Duration;
+use axum::extract::MatchedPath;
+use http::{HeaderMap, Request, Response};
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    trace::TracerProvider
+};
+use opentelemetry_otlp::{ExporterBuildError, Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{span_processor_with_async_runtime::BatchSpanProcessor, Sampler, SdkTracerProvider},
+    Resource
+};
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{MakeSpan, OnResponse, TraceLayer}
+};
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    fmt::{self, MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::{SubscriberInitExt, TryInitError},
+    EnvFilter, Layer
+};
+use crate::{
+    config::{LogConfig, LogFormat, OtelConfig, OtlpProtocol},
+    request_id::RequestId
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("failed to install tracing subscriber: {0}")]
+    Init(#[from] TryInitError),
+    #[error("failed to build OTLP exporter: {0}")]
+    Exporter(#[from] ExporterBuildError),
+}
+
+// - - - - - - - - - - - [SUBSCRIBER] - - - - - - - - - - -
+
+pub fn fmt_layer<S, W>(config: &LogConfig, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    match config.format {
+        LogFormat::Json => fmt::layer().json().with_current_span(true).with_span_list(false).with_writer(writer).boxed(),
+        LogFormat::Pretty => fmt::layer().pretty().with_writer(writer).boxed(),
+    }
+}
+
+// Exports spans in batches from a task on the current tokio runtime, which both the tonic and the async
+// reqwest clients require
+pub fn tracer_provider(config: &OtelConfig) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(config.endpoint())
+            .build()?,
+        OtlpProtocol::Http => SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(config.endpoint())
+            .build()?,
+    };
+
+    // Parent based sampling keeps the decision of an upstream caller that sent a traceparent header
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sampling_ratio)));
+
+    Ok(SdkTracerProvider::builder()
+        .with_span_processor(BatchSpanProcessor::builder(exporter, runtime::Tokio).build())
+        .with_sampler(sampler)
+        .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build())
+        .build())
+}
+
+pub fn otel_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+}
+
+// Keeps the tracer provider alive and flushes pending spans on shutdown
+#[must_use]
+pub struct TelemetryGuard {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl TelemetryGuard {
+    pub async fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            // Shutting down blocks until the export task has drained, so keep it off the async workers
+            let result = tokio::task::spawn_blocking(move || provider.shutdown()).await;
+            if let Ok(Err(error)) = result {
+                tracing::warn!(%error, "failed to flush spans");
+            }
+        }
+    }
+}
+
+// Logs go to stderr so commands such as `export` can write their data to stdout
+pub fn init(log: &LogConfig, otel: &OtelConfig) -> Result<TelemetryGuard, TelemetryError> {
+    let provider = if otel.enabled { Some(tracer_provider(otel)?) } else { None };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::new(&log.level))
+        .with(fmt_layer(log, std::io::stderr))
+        .with(provider.as_ref().map(otel_layer))
+        .try_init()?;
+
+    Ok(TelemetryGuard { provider })
+}
+
+// - - - - - - - - - - - [HTTP] - - - - - - - - - - -
+
+// Spans carry the route template rather than the URI, so emails in paths such as /users/:email never reach the logs
+#[derive(Debug, Clone, Copy)]
+pub struct RouteSpan;
+
+impl<B> MakeSpan<B> for RouteSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let path = request.extensions().get::<MatchedPath>()
+            .map(|matched| matched.as_str())
+            .unwrap_or("<unmatched>");
+        let request_id = RequestId::from_headers(request.headers())
+            .map(|id| id.0)
+            .unwrap_or_default();
+
+        let span = tracing::info_span!(
+            "http_request",
+            method = %request.method(),
+            path = %path,
+            request_id = %request_id,
+            status = tracing::field::Empty,
+        );
+
+        // Continues the caller's trace when a W3C traceparent header is present
+        let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+        let _ = span.set_parent(parent);
+        span
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogResponse;
+
+impl<B> OnResponse<B> for LogResponse {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        let status = response.status().as_u16();
+        span.record("status", status);
+        tracing::info!(status, latency_ms = latency.as_secs_f64() * 1000.0, "request completed");
+    }
+}
+
+pub fn trace_layer() -> TraceLayer<SharedClassifier<ServerErrorsAsFailures>, RouteSpan, (), LogResponse> {
+    TraceLayer::new_for_http()
+        .make_span_with(RouteSpan)
+        .on_request(())
+        .on_response(LogResponse)
+}
+
+// - - - - - - - - - - - [REDACTION] - - - - - - - - - - -
+
+// Keeps the first character of the local part and the domain, e.g. jerry@seinfeld.com becomes j***@seinfeld.com
+pub fn redact_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => match local.chars().next() {
+            Some(first) => format!("{}***@{}", first, domain),
+            None => format!("***@{}", domain),
+        },
+        None => "***".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_email() {
+        assert_eq!(redact_email("jerry@seinfeld.com"), "j***@seinfeld.com");
+        assert_eq!(redact_email("@manssiere.com"), "***@manssiere.com");
+        assert_eq!(redact_email("hellonewman"), "***");
+    }
+}
@@ -0,0 +1,175 @@
+use axum::http::{header, HeaderMap};
+use crate::{
+    extract::field_path,
+    format::Format,
+    storage::RepositoryError,
+    users::{
+        model::{BatchItemResult, BatchItemStatus, BatchMode, BatchReport, UpsertUser, validate_email},
+        service::{create_users, BatchOutcome, UserStore}
+    }
+};
+
+// How the items of a batch are framed in the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFormat {
+    // A single JSON array of users
+    JsonArray,
+    // One JSON user per line
+    Ndjson,
+}
+
+impl BatchFormat {
+    pub fn from_content_type(headers: &HeaderMap) -> Option<BatchFormat> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/x-ndjson" | "application/ndjson" => Some(BatchFormat::Ndjson),
+            _ if Format::from_media_type(&essence) == Some(Format::Json) => Some(BatchFormat::JsonArray),
+            _ => None,
+        }
+    }
+}
+
+// Why an item of a batch cannot be created, with the path of the offending field where there is one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalid {
+    pub reason: String,
+    pub field: Option<String>,
+}
+
+// Splits the body into items and validates each on its own, so one bad item does not reject the others.
+// Only a JSON array that is not well-formed rejects the body as a whole; a malformed NDJSON line is an
+// invalid item
+pub fn parse_items(format: BatchFormat, body: &[u8]) -> Result<Vec<Result<UpsertUser, Invalid>>, String> {
+    match format {
+        BatchFormat::JsonArray => {
+            let documents: Vec<serde_json::Value> = serde_json::from_slice(body)
+                .map_err(|error| format!("Expected a JSON array of users: {}", error))?;
+            Ok(documents.into_iter().map(validate_item).collect())
+        }
+        BatchFormat::Ndjson => Ok(body
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.trim_ascii().is_empty())
+            .map(|line| serde_json::from_slice(line)
+                .map_err(|error| Invalid { reason: format!("Invalid JSON: {}", error), field: None })
+                .and_then(validate_item))
+            .collect()),
+    }
+}
+
+// Applies the same rules as POST /users
+fn validate_item(document: serde_json::Value) -> Result<UpsertUser, Invalid> {
+    let user: UpsertUser = serde_path_to_error::deserialize(document).map_err(|error| Invalid {
+        field: field_path(&error),
+        reason: error.into_inner().to_string(),
+    })?;
+
+    if !validate_email(&user) {
+        return Err(Invalid { reason: "Invalid input for field 'email'".to_string(), field: Some("email".to_string()) });
+    }
+    Ok(user)
+}
+
+// Creates the valid items and reports on every item in the order of the body. An all-or-nothing batch with an
+// invalid item is rejected before the store is touched, as a single invalid POST /users would be
+pub async fn create_batch(items: Vec<Result<UpsertUser, Invalid>>, mode: BatchMode, shared_hashmap: &UserStore) -> Result<BatchReport, RepositoryError> {
+    let mut results: Vec<BatchItemResult> = items.iter().enumerate().map(|(index, item)| match item {
+        Ok(user) => BatchItemResult {
+            index,
+            status: BatchItemStatus::Skipped,
+            email: Some(user.email.clone()),
+            id: None,
+            reason: None,
+            field: None,
+        },
+        Err(invalid) => BatchItemResult {
+            index,
+            status: BatchItemStatus::Invalid,
+            email: None,
+            id: None,
+            reason: Some(invalid.reason.clone()),
+            field: invalid.field.clone(),
+        },
+    }).collect();
+
+    let any_invalid = items.iter().any(Result::is_err);
+    let (indices, requests): (Vec<usize>, Vec<UpsertUser>) = items.into_iter()
+        .enumerate()
+        .filter_map(|(index, item)| item.ok().map(|user| (index, user)))
+        .unzip();
+
+    if !(mode == BatchMode::AllOrNothing && any_invalid) {
+        let outcomes = create_users(requests, mode == BatchMode::AllOrNothing, shared_hashmap).await?;
+
+        for (index, outcome) in indices.into_iter().zip(outcomes) {
+            let result = &mut results[index];
+            match outcome {
+                BatchOutcome::Created(user) => {
+                    result.status = BatchItemStatus::Created;
+                    result.id = Some(user.id);
+                }
+                BatchOutcome::Duplicate => {
+                    result.status = BatchItemStatus::Duplicate;
+                    result.reason = Some("User with associated email already exists!".to_string());
+                }
+                BatchOutcome::Skipped => {}
+            }
+        }
+    }
+
+    let count = |status| results.iter().filter(|result| result.status == status).count();
+    Ok(BatchReport {
+        mode,
+        created: count(BatchItemStatus::Created),
+        duplicates: count(BatchItemStatus::Duplicate),
+        invalid: count(BatchItemStatus::Invalid),
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content_type(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_format_from_content_type() {
+        assert_eq!(BatchFormat::from_content_type(&content_type("application/json")), Some(BatchFormat::JsonArray));
+        assert_eq!(BatchFormat::from_content_type(&content_type("application/x-ndjson; charset=utf-8")), Some(BatchFormat::Ndjson));
+        assert_eq!(BatchFormat::from_content_type(&content_type("application/cbor")), None);
+        assert_eq!(BatchFormat::from_content_type(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_parse_ndjson_reports_each_line() {
+        let body = concat!(
+            r#"{"email": "jerry@seinfeld.com", "password": "superman", "fullname": "Jerry Seinfeld", "role": "comedian"}"#, "\r\n",
+            "\n",
+            r#"{"email": "kramer", "password": "giddyup", "fullname": "Cosmo Kramer", "role": "neighbor"}"#, "\n",
+            r#"{"email": "newman@usps.com", "password": "hello_jerry""#, "\n",
+            r#"{"email": "elaine@benes.com", "password": "get_out", "fullname": "Elaine Benes"}"#,
+        );
+
+        let items = parse_items(BatchFormat::Ndjson, body.as_bytes()).unwrap();
+
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].as_ref().unwrap().email, "jerry@seinfeld.com");
+        assert_eq!(items[1].as_ref().unwrap_err().field.as_deref(), Some("email"));
+        assert!(items[2].as_ref().unwrap_err().reason.starts_with("Invalid JSON"));
+        assert_eq!(items[3].as_ref().unwrap_err().field.as_deref(), Some("role"));
+    }
+
+    #[test]
+    fn test_parse_json_array_must_be_an_array() {
+        let body = r#"{"email": "jerry@seinfeld.com", "password": "superman", "fullname": "Jerry Seinfeld", "role": "comedian"}"#;
+
+        assert!(parse_items(BatchFormat::JsonArray, body.as_bytes()).is_err());
+        assert_eq!(parse_items(BatchFormat::JsonArray, b"[]").unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,220 @@
+use axum::{body::Bytes, http::HeaderMap};
+use futures::Stream;
+use crate::{
+    format::negotiate,
+    storage::{PageCursor, RepositoryError},
+    users::{
+        model::User,
+        service::{get_users_page, UserStore}
+    }
+};
+
+// Users read from the store per chunk of the response. Only one page is held in memory at a time
+pub const EXPORT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+    // In order of preference when a client accepts both equally
+    pub const ALL: [ExportFormat; 2] = [ExportFormat::Ndjson, ExportFormat::Csv];
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "ndjson" => Some(ExportFormat::Ndjson),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn negotiate(headers: &HeaderMap) -> Option<ExportFormat> {
+        negotiate(headers, &ExportFormat::ALL, ExportFormat::content_type, |format, media_type| {
+            let essence = media_type.split(';').next().unwrap_or_default().trim();
+            match format {
+                ExportFormat::Ndjson => matches!(essence, "application/x-ndjson" | "application/ndjson"),
+                ExportFormat::Csv => essence == "text/csv",
+            }
+        })
+    }
+}
+
+// The fields a user can be exported with. The password is deliberately not one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportField {
+    Id,
+    Email,
+    Fullname,
+    Role,
+}
+
+impl ExportField {
+    pub const ALL: [ExportField; 4] = [ExportField::Id, ExportField::Email, ExportField::Fullname, ExportField::Role];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportField::Id => "id",
+            ExportField::Email => "email",
+            ExportField::Fullname => "fullname",
+            ExportField::Role => "role",
+        }
+    }
+
+    fn value(self, user: &User) -> serde_json::Value {
+        match self {
+            ExportField::Id => user.id.into(),
+            ExportField::Email => user.email.as_str().into(),
+            ExportField::Fullname => user.fullname.as_str().into(),
+            ExportField::Role => user.role.as_str().into(),
+        }
+    }
+}
+
+// Parses a comma-separated field selection such as "email,fullname", keeping its order. Every field is
+// exported when there is no selection
+pub fn parse_fields(selection: Option<&str>) -> Result<Vec<ExportField>, String> {
+    let Some(selection) = selection else {
+        return Ok(ExportField::ALL.to_vec());
+    };
+
+    let mut fields = Vec::new();
+    for name in selection.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let field = ExportField::ALL.into_iter()
+            .find(|field| field.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let known: Vec<&str> = ExportField::ALL.iter().map(|field| field.name()).collect();
+                format!("Unknown export field '{}', expected some of {}", name, known.join(", "))
+            })?;
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+
+    if fields.is_empty() {
+        return Err("Select at least one field to export".to_string());
+    }
+    Ok(fields)
+}
+
+// Streams every user in the order the backend pages them, which is by email for all but the table backend, one
+// page per chunk. Users created or deleted while the export runs appear or not depending on whether their page has
+// been read yet. A page the storage backend fails to read ends the stream with its error, which aborts the response
+// since the status has already been sent
+pub fn export_stream(
+    shared_hashmap: UserStore,
+    format: ExportFormat,
+    fields: Vec<ExportField>
+) -> impl Stream<Item = Result<Bytes, RepositoryError>> {
+    struct Cursor {
+        next: Option<PageCursor>,
+        header_written: bool,
+        done: bool,
+    }
+
+    let cursor = Cursor { next: None, header_written: false, done: false };
+
+    futures::stream::unfold(cursor, move |mut cursor| {
+        let shared_hashmap = shared_hashmap.clone();
+        let fields = fields.clone();
+
+        async move {
+            if cursor.done {
+                return None;
+            }
+
+            let page = match get_users_page(cursor.next.as_ref(), EXPORT_PAGE_SIZE, &shared_hashmap).await {
+                Ok(page) => page,
+                Err(error) => {
+                    tracing::error!(%error, "export failed");
+                    cursor.done = true;
+                    return Some((Err(error), cursor));
+                }
+            };
+            cursor.done = page.next.is_none();
+            cursor.next = page.next;
+
+            let mut chunk = Vec::new();
+            if format == ExportFormat::Csv && !cursor.header_written {
+                write_csv_record(&mut chunk, fields.iter().map(|field| field.name().to_string()));
+            }
+            cursor.header_written = true;
+
+            for user in &page.users {
+                match format {
+                    ExportFormat::Ndjson => write_json_line(&mut chunk, &fields, user),
+                    ExportFormat::Csv => write_csv_record(&mut chunk, fields.iter().map(|field| match field.value(user) {
+                        serde_json::Value::String(value) => value,
+                        value => value.to_string(),
+                    })),
+                }
+            }
+
+            Some((Ok(Bytes::from(chunk)), cursor))
+        }
+    })
+}
+
+// Writes the keys in the order of the selection, which a serde_json::Map would sort
+fn write_json_line(chunk: &mut Vec<u8>, fields: &[ExportField], user: &User) {
+    chunk.push(b'{');
+    for (position, field) in fields.iter().enumerate() {
+        if position > 0 {
+            chunk.push(b',');
+        }
+        chunk.extend_from_slice(serde_json::Value::from(field.name()).to_string().as_bytes());
+        chunk.push(b':');
+        chunk.extend_from_slice(field.value(user).to_string().as_bytes());
+    }
+    chunk.extend_from_slice(b"}\n");
+}
+
+fn write_csv_record(chunk: &mut Vec<u8>, record: impl IntoIterator<Item = String>) {
+    let mut writer = csv::Writer::from_writer(chunk);
+    // Writing into memory cannot fail
+    writer.write_record(record).expect("writes to a Vec");
+    writer.flush().expect("writes to a Vec");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(ExportFormat::negotiate(&HeaderMap::new()), Some(ExportFormat::Ndjson));
+        assert_eq!(ExportFormat::negotiate(&accept("text/*")), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::negotiate(&accept("application/json")), None);
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(parse_fields(None).unwrap(), ExportField::ALL);
+        assert_eq!(parse_fields(Some("role, Email,role")).unwrap(), [ExportField::Role, ExportField::Email]);
+        assert!(parse_fields(Some("email,password")).unwrap_err().contains("password"));
+        assert!(parse_fields(Some(" , ")).is_err());
+    }
+
+    #[test]
+    fn test_csv_values_are_quoted() {
+        let mut chunk = Vec::new();
+        write_csv_record(&mut chunk, ["Kramer, Cosmo".to_string(), "says \"giddyup\"".to_string()]);
+
+        assert_eq!(String::from_utf8(chunk).unwrap(), "\"Kramer, Cosmo\",\"says \"\"giddyup\"\"\"\n");
+    }
+}
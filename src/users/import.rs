@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use crate::{
+    storage::RepositoryError,
+    users::{
+        model::{ImportAction, ImportReport, ImportRowResult, UpsertUser, validate_email},
+        service::{create_user, get_user_by_email, update_user_by_email, UserStore}
+    }
+};
+
+// Attempts to create or update a user that keeps appearing and disappearing under the import
+const UPSERT_ATTEMPTS: usize = 3;
+
+// The fields of UpsertUser a CSV column can be mapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserField {
+    Email,
+    Password,
+    Fullname,
+    Role,
+}
+
+impl UserField {
+    pub const ALL: [UserField; 4] = [UserField::Email, UserField::Password, UserField::Fullname, UserField::Role];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UserField::Email => "email",
+            UserField::Password => "password",
+            UserField::Fullname => "fullname",
+            UserField::Role => "role",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<UserField> {
+        UserField::ALL.into_iter().find(|field| field.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    // Headers spreadsheets commonly use for the field, compared after normalize
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            UserField::Email => &["email", "emailaddress", "mail"],
+            UserField::Password => &["password", "pass", "passwd", "pwd"],
+            UserField::Fullname => &["fullname", "name", "displayname"],
+            UserField::Role => &["role", "title", "jobtitle", "position"],
+        }
+    }
+}
+
+// "E-mail Address" and "email_address" both become "emailaddress"
+fn normalize(header: &str) -> String {
+    header.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+// Which CSV column holds which field. Columns named in the mapping go to the given field, the others are
+// recognized by their header and ignored when it is not one the importer knows
+#[derive(Debug, Clone, Default)]
+pub struct ColumnMapping {
+    overrides: Vec<(String, UserField)>,
+}
+
+impl ColumnMapping {
+    // Parses pairs such as "E-mail Address=email", matching the header exactly apart from case
+    pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = &'a str>) -> Result<ColumnMapping, String> {
+        let overrides = pairs.into_iter()
+            .filter(|pair| !pair.trim().is_empty())
+            .map(|pair| {
+                let (header, field) = pair.rsplit_once('=')
+                    .ok_or_else(|| format!("Expected a column mapping as Header=field, got '{}'", pair))?;
+                let field = UserField::from_name(field)
+                    .ok_or_else(|| format!("Cannot map column '{}' to unknown field '{}'", header.trim(), field.trim()))?;
+                Ok((header.trim().to_string(), field))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(ColumnMapping { overrides })
+    }
+
+    // A comma-separated list of pairs, as taken by the map parameter of POST /users/import
+    pub fn parse(spec: &str) -> Result<ColumnMapping, String> {
+        ColumnMapping::from_pairs(spec.split(','))
+    }
+
+    // The column index of every field, in the order of UserField::ALL
+    fn resolve(&self, headers: &csv::StringRecord) -> Result<[usize; 4], String> {
+        let mut columns: [Option<usize>; 4] = [None; 4];
+
+        for (index, header) in headers.iter().enumerate() {
+            let field = match self.overrides.iter().find(|(name, _)| name.eq_ignore_ascii_case(header.trim())) {
+                Some((_, field)) => Some(*field),
+                None => UserField::ALL.into_iter().find(|field| field.aliases().contains(&normalize(header).as_str())),
+            };
+            let Some(field) = field else { continue };
+
+            let slot = &mut columns[field as usize];
+            if let Some(previous) = *slot {
+                return Err(format!("Both columns '{}' and '{}' map to {}", &headers[previous], header, field.name()));
+            }
+            *slot = Some(index);
+        }
+
+        let mut resolved = [0; 4];
+        for field in UserField::ALL {
+            resolved[field as usize] = columns[field as usize].ok_or_else(|| {
+                format!("No column maps to {0}, name one with Header={0}", field.name())
+            })?;
+        }
+        Ok(resolved)
+    }
+}
+
+// Creates the users of a CSV file that do not exist yet and updates those that do, keyed by email. With dry_run
+// the store is only read, and the report tells what the import would do. A header row is required; rows are
+// numbered by their line in the file. A row the storage backend fails on is rejected with the failure as reason
+pub async fn import_csv(body: &[u8], mapping: &ColumnMapping, dry_run: bool, store: &UserStore) -> Result<ImportReport, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(body);
+    let headers = reader.headers().map_err(|error| format!("Invalid CSV header: {}", error))?.clone();
+    let columns = mapping.resolve(&headers)?;
+
+    let mut report = ImportReport { dry_run, created: 0, updated: 0, rejected: 0, rows: Vec::new() };
+    // Emails a dry run would have created, so a repeated email counts as an update as it would for real
+    let mut created_emails = HashSet::new();
+
+    for record in reader.records() {
+        let (row, result) = match record {
+            Ok(record) => {
+                let row = record.position().map(|position| position.line()).unwrap_or_default();
+                (row, parse_row(&record, &columns))
+            }
+            Err(error) => {
+                let row = error.position().map(|position| position.line()).unwrap_or_default();
+                (row, Err((None, format!("Invalid CSV row: {}", error))))
+            }
+        };
+
+        let (email, action, reason) = match result {
+            Err((email, reason)) => (email, ImportAction::Rejected, Some(reason)),
+            Ok(request) if dry_run => {
+                let email = request.email.clone();
+                let exists = match created_emails.contains(&email) {
+                    true => Ok(true),
+                    false => get_user_by_email(&email, store).await.map(|user| user.is_some()),
+                };
+                match exists {
+                    Ok(true) => (Some(email), ImportAction::Updated, None),
+                    Ok(false) => {
+                        created_emails.insert(email.clone());
+                        (Some(email), ImportAction::Created, None)
+                    }
+                    Err(error) => (Some(email), ImportAction::Rejected, Some(storage_failure(error))),
+                }
+            }
+            Ok(request) => {
+                let email = request.email.clone();
+                let (action, reason) = upsert(request, store).await;
+                (Some(email), action, reason)
+            }
+        };
+
+        match action {
+            ImportAction::Created => report.created += 1,
+            ImportAction::Updated => report.updated += 1,
+            ImportAction::Rejected => report.rejected += 1,
+        }
+        report.rows.push(ImportRowResult { row, email, action, reason });
+    }
+
+    Ok(report)
+}
+
+fn parse_row(record: &csv::StringRecord, columns: &[usize; 4]) -> Result<UpsertUser, (Option<String>, String)> {
+    let value = |field: UserField| record.get(columns[field as usize]).unwrap_or_default().to_string();
+    let email = value(UserField::Email);
+    let reject = |reason: String| (Some(email.clone()).filter(|email| !email.is_empty()), reason);
+
+    if let Some(field) = UserField::ALL.into_iter().find(|field| value(*field).is_empty()) {
+        return Err(reject(format!("Missing value for {}", field.name())));
+    }
+
+    let request = UpsertUser {
+        email: email.clone(),
+        password: value(UserField::Password),
+        fullname: value(UserField::Fullname),
+        role: value(UserField::Role),
+    };
+    if !validate_email(&request) {
+        return Err(reject("Invalid input for field 'email'".to_string()));
+    }
+    Ok(request)
+}
+
+// Creating first and falling back to an update covers a user created by someone else in the meantime;
+// the update is retried as a create for one deleted in the meantime
+async fn upsert(request: UpsertUser, store: &UserStore) -> (ImportAction, Option<String>) {
+    let email = request.email.clone();
+    for _ in 0..UPSERT_ATTEMPTS {
+        match create_user(request.clone(), store).await {
+            Ok(_) => return (ImportAction::Created, None),
+            Err(RepositoryError::Duplicate) => {}
+            Err(error) => return (ImportAction::Rejected, Some(storage_failure(error))),
+        }
+        match update_user_by_email(&email, request.clone(), store).await {
+            Ok(Some(_)) => return (ImportAction::Updated, None),
+            Ok(None) => {}
+            Err(error) => return (ImportAction::Rejected, Some(storage_failure(error))),
+        }
+    }
+    (ImportAction::Rejected, Some("The user was created and deleted concurrently, try again".to_string()))
+}
+
+// The cause is logged rather than reported, since it may name hosts or tables of the backend
+fn storage_failure(error: RepositoryError) -> String {
+    tracing::error!(%error, "storage backend failed during import");
+    "Storage is unavailable, try again later".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(names: &[&str]) -> csv::StringRecord {
+        csv::StringRecord::from(names.to_vec())
+    }
+
+    #[test]
+    fn test_headers_are_recognized() {
+        let columns = ColumnMapping::default()
+            .resolve(&headers(&["Job Title", "Notes", "E-mail Address", "Name", "Password"]))
+            .unwrap();
+
+        assert_eq!(columns, [2, 4, 3, 0]);
+    }
+
+    #[test]
+    fn test_mapping_overrides_headers() {
+        let mapping = ColumnMapping::parse("Contact=email, Secret=password,Name=role").unwrap();
+        let columns = mapping.resolve(&headers(&["Contact", "Secret", "Name", "Full Name"])).unwrap();
+
+        assert_eq!(columns, [0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn test_mapping_problems() {
+        assert!(ColumnMapping::parse("Contact").is_err());
+        assert!(ColumnMapping::parse("Contact=phone").unwrap_err().contains("phone"));
+
+        let error = ColumnMapping::default().resolve(&headers(&["email", "mail", "password", "name", "role"])).unwrap_err();
+        assert!(error.contains("'email' and 'mail'"));
+
+        let error = ColumnMapping::default().resolve(&headers(&["email", "password", "name"])).unwrap_err();
+        assert!(error.contains("role"));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod batch;
+pub mod export;
+pub mod import;
 pub mod router;
 pub mod service;
 pub mod model;
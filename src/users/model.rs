@@ -1,7 +1,9 @@
 use regex::Regex;
 use serde_derive::{Serialize, Deserialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize)]
+// Also the record of storage snapshots: a field added here needs #[serde(default)] for older snapshots to load
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct User {
     pub id: i32,
     pub email: String,
@@ -10,7 +12,7 @@ pub struct User {
     pub role: String
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct UpsertUser {
     pub email: String,
     pub password: String,
@@ -29,6 +31,95 @@ pub fn validate_email(body: &UpsertUser) -> bool {
     body.is_valid_email()
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+    pub request_id: String,
+    // Path of the offending field when a request body is rejected, e.g. "role"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MessageBody {
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    // Nothing is created unless every item can be
+    #[default]
+    AllOrNothing,
+    // Every item that can be created is
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Created,
+    Duplicate,
+    Invalid,
+    // Valid and not a duplicate, but not created since the all-or-nothing batch failed
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchItemResult {
+    // Position of the item in the array or among the lines of the body, starting at 0
+    pub index: usize,
+    pub status: BatchItemStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    // Why an item is invalid or a duplicate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    // Path of the offending field of an invalid item, as in ErrorBody
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BatchReport {
+    pub mode: BatchMode,
+    pub created: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportAction {
+    Created,
+    Updated,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImportRowResult {
+    // Line of the row in the file, the header being line 1
+    pub row: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    // What the import did, or would do in a dry run
+    pub action: ImportAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub rejected: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
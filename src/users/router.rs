@@ -1,80 +1,414 @@
-use std::{
-    sync::{Arc, Mutex},
-    collections::HashMap
-};
 use axum::{
-    extract::{State, Path},
-    http::StatusCode,
-    response::IntoResponse,
+    body::{Bytes, StreamBody},
+    extract::{rejection::{BytesRejection, QueryRejection}, State, Path, Query},
+    http::{header, uri::PathAndQuery, HeaderMap, Method, Request, StatusCode, Uri},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put, MethodRouter},
     Router,
     Json
 };
-use serde_json::{json, Value};
-use crate::users::{
-    model::{UpsertUser, User, validate_email},
-    service::{create_user, get_user_by_email, delete_user_by_email, update_user_by_email},
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use crate::{
+    api::unversioned_path,
+    extract::ApiBody,
+    format::{Encoded, ResponseFormat},
+    metrics::track_http_metrics,
+    request_id::{RequestId, REQUEST_ID_HEADER},
+    storage::RepositoryError,
+    telemetry::trace_layer,
+    users::{
+        batch::{create_batch, parse_items, BatchFormat},
+        export::{export_stream, parse_fields, ExportFormat},
+        import::{import_csv, ColumnMapping},
+        model::{BatchMode, BatchReport, ErrorBody, ImportReport, MessageBody, UpsertUser, User, validate_email},
+        service::{UserStore, create_user, get_user_by_email, delete_user_by_email, update_user_by_email},
+    }
 };
 
 // - - - - - - - - - - - [ROUTES] - - - - - - - - - - -
 
-pub fn users_routes(shared_hashmap: Arc<Mutex<HashMap<String, User>>>) -> Router {
-    Router::new()
-        .route("/users", axum::routing::post(create_user_handler))
-        .route("/users/:email", axum::routing::get(get_user_handler))
-        .route("/users/:email", axum::routing::put(update_user_handler))
-        .route("/users/:email", axum::routing::delete(delete_user_handler))
+// Every (method, path) the users router serves, which the OpenAPI document must list exactly
+pub fn users_route_table() -> Vec<(Method, &'static str, MethodRouter<UserStore>)> {
+    vec![
+        (Method::POST, "/users", post(create_user_handler)),
+        (Method::POST, "/users/batch", post(create_users_handler)),
+        (Method::GET, "/users/export", get(export_users_handler)),
+        (Method::POST, "/users/import", post(import_users_handler)),
+        (Method::GET, "/users/:email", get(get_user_handler)),
+        (Method::PUT, "/users/:email", put(update_user_handler)),
+        (Method::DELETE, "/users/:email", delete(delete_user_handler)),
+    ]
+}
+
+// Clients may post batches to /users:batch as well. The router cannot match a literal colon, so the server rewrites
+// those requests to /users/batch before routing them
+pub fn rewrite_batch_alias<B>(mut request: Request<B>) -> Request<B> {
+    let path = request.uri().path();
+    if unversioned_path(path) != "/users:batch" {
+        return request;
+    }
+
+    let mut rewritten = format!("{}/batch", &path[..path.len() - ":batch".len()]);
+    if let Some(query) = request.uri().query() {
+        rewritten.push('?');
+        rewritten.push_str(query);
+    }
+    let Ok(path_and_query) = rewritten.parse::<PathAndQuery>() else {
+        return request;
+    };
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = Some(path_and_query);
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *request.uri_mut() = uri;
+    }
+    request
+}
+
+pub fn users_routes(shared_hashmap: UserStore) -> Router {
+    users_route_table().into_iter()
+        .fold(Router::new(), |router, (_, path, handler)| router.route(path, handler))
+        .layer(middleware::from_fn(track_http_metrics))
+        .layer(trace_layer())
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
         .with_state(shared_hashmap)
 }
 
+// Every error body carries the request id so a failed call reported by a user can be found in the logs
+fn error_body(request_id: &RequestId, message: &str) -> Json<ErrorBody> {
+    Json(ErrorBody { error: message.to_string(), request_id: request_id.0.clone(), field: None })
+}
+
+// The cause is logged rather than returned, since it may name hosts or tables of the backend
+fn storage_error(request_id: &RequestId, error: RepositoryError) -> (StatusCode, Json<ErrorBody>) {
+    tracing::error!(%error, "storage backend failed");
+    (StatusCode::SERVICE_UNAVAILABLE, error_body(request_id, "Storage is unavailable, try again later"))
+}
+
 // - - - - - - - - - - - [HANDLERS] - - - - - - - - - - -
 
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body(content(
+        (UpsertUser = "application/json"),
+        (UpsertUser = "application/cbor"),
+        (UpsertUser = "application/msgpack")
+    )),
+    responses(
+        (status = 201, description = "User created", content(
+            (User = "application/json"),
+            (User = "application/cbor"),
+            (User = "application/msgpack")
+        )),
+        (status = 208, description = "A user with the same email already exists", body = ErrorBody),
+        (status = 406, description = "None of the types in Accept is supported", body = ErrorBody),
+        (status = 400, description = "The body is not well-formed in its Content-Type", body = ErrorBody),
+        (status = 413, description = "The body exceeds limits.max_body_bytes", body = ErrorBody),
+        (status = 415, description = "The body is not sent as JSON, CBOR or MessagePack", body = ErrorBody),
+        (status = 422, description = "A field is missing, has the wrong type or the email is not valid", body = ErrorBody),
+        (status = 503, description = "The storage backend failed", body = ErrorBody)
+    )
+)]
 pub async fn create_user_handler(
-    State(shared_hashmap): State<Arc<Mutex<HashMap<String, User>>>>,
-    Json(request): Json<UpsertUser>,
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    ResponseFormat(format): ResponseFormat,
+    ApiBody(request): ApiBody<UpsertUser>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorBody>)> {
     if !validate_email(&request) {
-        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(json!({"error": "Invalid input for field 'email'"}))));
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, error_body(&request_id, "Invalid input for field 'email'")));
     }
 
     match create_user(request, &shared_hashmap).await {
-        None => Err((StatusCode::ALREADY_REPORTED, Json(json!({"error": "User with associated email already exists!"})))),
-        Some(created_user) => Ok((StatusCode::CREATED, Json(created_user)))
+        Err(RepositoryError::Duplicate) => Err((StatusCode::ALREADY_REPORTED, error_body(&request_id, "User with associated email already exists!"))),
+        Err(error) => Err(storage_error(&request_id, error)),
+        Ok(created_user) => Ok((StatusCode::CREATED, Encoded(format, created_user)))
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, serde_derive::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BatchQuery {
+    // all_or_nothing unless given
+    #[serde(default)]
+    pub mode: BatchMode,
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/batch",
+    tag = "users",
+    params(BatchQuery),
+    request_body(description = "A JSON array of users, or one JSON user per line", content(
+        (Vec<UpsertUser> = "application/json"),
+        (UpsertUser = "application/x-ndjson")
+    )),
+    responses(
+        (status = 201, description = "Every user created", content(
+            (BatchReport = "application/json"),
+            (BatchReport = "application/cbor"),
+            (BatchReport = "application/msgpack")
+        )),
+        (status = 207, description = "Best effort: the users that could be created were, see the report", content(
+            (BatchReport = "application/json"),
+            (BatchReport = "application/cbor"),
+            (BatchReport = "application/msgpack")
+        )),
+        (status = 400, description = "The body is not a JSON array, or the mode is unknown", body = ErrorBody),
+        (status = 406, description = "None of the types in Accept is supported", body = ErrorBody),
+        (status = 413, description = "The body exceeds limits.max_body_bytes", body = ErrorBody),
+        (status = 415, description = "The body is not sent as JSON or NDJSON", body = ErrorBody),
+        (status = 422, description = "All or nothing: an item is invalid or a duplicate, nothing was created", content(
+            (BatchReport = "application/json"),
+            (BatchReport = "application/cbor"),
+            (BatchReport = "application/msgpack")
+        )),
+        (status = 503, description = "The storage backend failed", body = ErrorBody)
+    )
+)]
+pub async fn create_users_handler(
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    ResponseFormat(format): ResponseFormat,
+    query: Result<Query<BatchQuery>, QueryRejection>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorBody>)> {
+    let Query(query) = query.map_err(|rejection| (StatusCode::BAD_REQUEST, error_body(&request_id, &rejection.body_text())))?;
+    let Some(batch_format) = BatchFormat::from_content_type(&headers) else {
+        let message = "Expected a request with a Content-Type of application/json or application/x-ndjson";
+        return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, error_body(&request_id, message)));
+    };
+    let body = body.map_err(|rejection| match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => (StatusCode::PAYLOAD_TOO_LARGE, error_body(&request_id, "Request body is too large")),
+        status => (status, error_body(&request_id, &rejection.body_text())),
+    })?;
+
+    let items = parse_items(batch_format, &body)
+        .map_err(|error| (StatusCode::BAD_REQUEST, error_body(&request_id, &error)))?;
+    let count = items.len();
+    let report = create_batch(items, query.mode, &shared_hashmap).await
+        .map_err(|error| storage_error(&request_id, error))?;
+
+    let status = if report.created == count {
+        StatusCode::CREATED
+    } else if query.mode == BatchMode::AllOrNothing {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    Ok((status, Encoded(format, report)))
+}
+
+#[derive(Debug, Clone, Default, serde_derive::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    // ndjson or csv, taking precedence over Accept
+    pub format: Option<String>,
+    // Comma-separated fields to export, out of id, email, fullname and role. All of them unless given
+    pub fields: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/export",
+    tag = "users",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Every user ordered by email (by domain first with the table backend), without passwords, streamed as it is read", content(
+            (String = "application/x-ndjson"),
+            (String = "text/csv")
+        )),
+        (status = 400, description = "The format or a field is unknown", body = ErrorBody),
+        (status = 406, description = "Neither NDJSON nor CSV is accepted and no format is given", body = ErrorBody)
+    )
+)]
+pub async fn export_users_handler(
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+    headers: HeaderMap
+) -> Result<Response, (StatusCode, Json<ErrorBody>)> {
+    let Query(query) = query.map_err(|rejection| (StatusCode::BAD_REQUEST, error_body(&request_id, &rejection.body_text())))?;
+
+    let export_format = match query.format.as_deref() {
+        Some(name) => ExportFormat::from_name(name).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, error_body(&request_id, &format!("Unknown export format '{}', expected ndjson or csv", name)))
+        })?,
+        None => ExportFormat::negotiate(&headers).ok_or_else(|| {
+            let message = "None of the accepted media types is supported, expected one of application/x-ndjson, text/csv";
+            (StatusCode::NOT_ACCEPTABLE, error_body(&request_id, message))
+        })?,
+    };
+    let fields = parse_fields(query.fields.as_deref())
+        .map_err(|error| (StatusCode::BAD_REQUEST, error_body(&request_id, &error)))?;
+
+    let disposition = match export_format {
+        ExportFormat::Ndjson => "attachment; filename=\"users.ndjson\"",
+        ExportFormat::Csv => "attachment; filename=\"users.csv\"",
+    };
+    let headers = [(header::CONTENT_TYPE, export_format.content_type()), (header::CONTENT_DISPOSITION, disposition)];
+    Ok((headers, StreamBody::new(export_stream(shared_hashmap, export_format, fields))).into_response())
+}
+
+#[derive(Debug, Clone, Default, serde_derive::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    // Reports what the import would do without changing any user
+    #[serde(default)]
+    pub dry_run: bool,
+    // Comma-separated Header=field pairs for columns whose header is not recognized, e.g. "Contact=email"
+    pub map: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/import",
+    tag = "users",
+    params(ImportQuery),
+    request_body(description = "A CSV file with a header row", content((String = "text/csv"))),
+    responses(
+        (status = 200, description = "Users created or updated by email, or what would be in a dry run", content(
+            (ImportReport = "application/json"),
+            (ImportReport = "application/cbor"),
+            (ImportReport = "application/msgpack")
+        )),
+        (status = 400, description = "The header row or the column mapping leaves a field without a column", body = ErrorBody),
+        (status = 406, description = "None of the types in Accept is supported", body = ErrorBody),
+        (status = 413, description = "The body exceeds limits.max_body_bytes", body = ErrorBody),
+        (status = 415, description = "The body is not sent as text/csv", body = ErrorBody)
+    )
+)]
+pub async fn import_users_handler(
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    ResponseFormat(format): ResponseFormat,
+    query: Result<Query<ImportQuery>, QueryRejection>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorBody>)> {
+    let Query(query) = query.map_err(|rejection| (StatusCode::BAD_REQUEST, error_body(&request_id, &rejection.body_text())))?;
+    let is_csv = headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case("text/csv"));
+    if !is_csv {
+        return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, error_body(&request_id, "Expected a request with a Content-Type of text/csv")));
+    }
+    let body = body.map_err(|rejection| match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => (StatusCode::PAYLOAD_TOO_LARGE, error_body(&request_id, "Request body is too large")),
+        status => (status, error_body(&request_id, &rejection.body_text())),
+    })?;
+
+    let mapping = ColumnMapping::parse(query.map.as_deref().unwrap_or_default())
+        .map_err(|error| (StatusCode::BAD_REQUEST, error_body(&request_id, &error)))?;
+    let report = import_csv(&body, &mapping, query.dry_run, &shared_hashmap).await
+        .map_err(|error| (StatusCode::BAD_REQUEST, error_body(&request_id, &error)))?;
+
+    Ok((StatusCode::OK, Encoded(format, report)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{email}",
+    tag = "users",
+    params(("email" = String, Path, description = "Email address of the user")),
+    responses(
+        (status = 200, description = "User found", content(
+            (User = "application/json"),
+            (User = "application/cbor"),
+            (User = "application/msgpack")
+        )),
+        (status = 404, description = "No user with this email", body = ErrorBody),
+        (status = 406, description = "None of the types in Accept is supported", body = ErrorBody),
+        (status = 503, description = "The storage backend failed", body = ErrorBody)
+    )
+)]
 pub async fn get_user_handler(
-    State(shared_hashmap): State<Arc<Mutex<HashMap<String, User>>>>,
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    ResponseFormat(format): ResponseFormat,
     path: Path<String>
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorBody>)> {
     let email = path.0;
 
     match get_user_by_email(&email, &shared_hashmap).await {
-        Some(user) => Ok((StatusCode::OK, Json(user))),
-        _ => Err((StatusCode::NOT_FOUND, Json(json!({"error": "User not found"}))))
+        Ok(Some(user)) => Ok((StatusCode::OK, Encoded(format, user))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, error_body(&request_id, "User not found"))),
+        Err(error) => Err(storage_error(&request_id, error))
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/users/{email}",
+    tag = "users",
+    params(("email" = String, Path, description = "Email address of the user")),
+    request_body(content(
+        (UpsertUser = "application/json"),
+        (UpsertUser = "application/cbor"),
+        (UpsertUser = "application/msgpack")
+    )),
+    responses(
+        (status = 200, description = "User updated", content(
+            (User = "application/json"),
+            (User = "application/cbor"),
+            (User = "application/msgpack")
+        )),
+        (status = 400, description = "The body is not well-formed in its Content-Type", body = ErrorBody),
+        (status = 404, description = "No user with this email", body = ErrorBody),
+        (status = 406, description = "None of the types in Accept is supported", body = ErrorBody),
+        (status = 413, description = "The body exceeds limits.max_body_bytes", body = ErrorBody),
+        (status = 415, description = "The body is not sent as JSON, CBOR or MessagePack", body = ErrorBody),
+        (status = 422, description = "A field is missing or has the wrong type", body = ErrorBody),
+        (status = 503, description = "The storage backend failed", body = ErrorBody)
+    )
+)]
 pub async fn update_user_handler(
-    State(shared_hashmap): State<Arc<Mutex<HashMap<String, User>>>>,
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    ResponseFormat(format): ResponseFormat,
     path: Path<String>,
-    Json(request): Json<UpsertUser>
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+    ApiBody(request): ApiBody<UpsertUser>
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorBody>)> {
     let email = path.0;
 
     match update_user_by_email(&email, request, &shared_hashmap).await {
-        Some(retrieved_user) => Ok((StatusCode::OK, Json(retrieved_user))),
-        _ => Err((StatusCode::NOT_FOUND, Json(json!({"error": "User not found"}))))
+        Ok(Some(retrieved_user)) => Ok((StatusCode::OK, Encoded(format, retrieved_user))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, error_body(&request_id, "User not found"))),
+        Err(error) => Err(storage_error(&request_id, error))
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/users/{email}",
+    tag = "users",
+    params(("email" = String, Path, description = "Email address of the user")),
+    responses(
+        (status = 200, description = "User deleted", content(
+            (MessageBody = "application/json"),
+            (MessageBody = "application/cbor"),
+            (MessageBody = "application/msgpack")
+        )),
+        (status = 404, description = "No user with this email", body = ErrorBody),
+        (status = 406, description = "None of the types in Accept is supported", body = ErrorBody),
+        (status = 503, description = "The storage backend failed", body = ErrorBody)
+    )
+)]
 pub async fn delete_user_handler(
-    State(shared_hashmap): State<Arc<Mutex<HashMap<String, User>>>>,
+    State(shared_hashmap): State<UserStore>,
+    request_id: RequestId,
+    ResponseFormat(format): ResponseFormat,
     path: Path<String>
-) -> Result<impl IntoResponse, (StatusCode, Json<Value>)> {
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorBody>)> {
     let email = path.0;
 
     match delete_user_by_email(&email, &shared_hashmap).await {
-        Some(_user) => Ok((StatusCode::OK, Json(json!({"message": "User has been deleted"})))),
-        _ => Err((StatusCode::NOT_FOUND, Json(json!({"error": "User not found"}))))
+        Ok(Some(_user)) => Ok((StatusCode::OK, Encoded(format, MessageBody { message: "User has been deleted".to_string() }))),
+        Ok(None) => Err((StatusCode::NOT_FOUND, error_body(&request_id, "User not found"))),
+        Err(error) => Err(storage_error(&request_id, error))
     }
 }
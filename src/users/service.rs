@@ -1,78 +1,139 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use crate::users::model::{User, UpsertUser};
-
-pub async fn create_user(request: UpsertUser, shared_hashmap: &Arc<Mutex<HashMap<String, User>>>) -> Option<User> {
-    let mut acquired_map = shared_hashmap.lock().unwrap_or_else(|_| {
-        println!("Error unwrapping Option!");
-        panic!("Mutex lock failed");
-    });
-
-    if let Some(_user) = acquired_map.get(&request.email) {
-        None
-    } else {
-        let new_user = User {
-            id: (acquired_map.len() as i32) + 1,
-            email: request.email.clone(),
-            password: request.password,
-            fullname: request.fullname,
-            role: request.role,
-        };
-        acquired_map.insert(request.email, new_user.clone());
-        Some(new_user)
+use std::{
+    collections::HashMap,
+    sync::Arc
+};
+use crate::{
+    metrics::STORE_OPERATION_DURATION,
+    storage::{memory::MemoryRepository, PageCursor, RepositoryError, UserPage, UserRepository},
+    telemetry::redact_email,
+    users::model::{User, UpsertUser}
+};
+
+// Handle to the users, wherever the configured backend keeps them. Clones share the same backend
+#[derive(Clone)]
+pub struct UserStore {
+    repository: Arc<dyn UserRepository>,
+    // Set for the memory backend, which snapshots and the write-ahead log work on directly
+    memory: Option<Arc<MemoryRepository>>,
+}
+
+impl Default for UserStore {
+    fn default() -> Self {
+        UserStore::new()
     }
 }
 
-pub async fn get_user_by_email(email: &String, shared_hashmap: &Arc<Mutex<HashMap<String, User>>>) -> Option<User> {
-    let acquired_map = shared_hashmap.lock().unwrap_or_else(|_| {
-        println!("Error unwrapping Option!");
-        panic!("Mutex lock failed");
-    });
-    acquired_map.get(email).cloned()
+impl UserStore {
+    // An empty store in the memory of this process
+    pub fn new() -> Self {
+        UserStore::with_users(HashMap::new())
+    }
+
+    pub fn with_users(users: HashMap<String, User>) -> Self {
+        UserStore::from_memory(MemoryRepository::new(users))
+    }
+
+    pub fn from_memory(memory: MemoryRepository) -> Self {
+        let memory = Arc::new(memory);
+        UserStore { repository: memory.clone(), memory: Some(memory) }
+    }
+
+    pub fn from_repository(repository: Arc<dyn UserRepository>) -> Self {
+        UserStore { repository, memory: None }
+    }
+
+    pub fn repository(&self) -> &Arc<dyn UserRepository> {
+        &self.repository
+    }
+
+    pub fn memory(&self) -> Option<&MemoryRepository> {
+        self.memory.as_deref()
+    }
 }
 
-pub async fn update_user_by_email(email: &String, request: UpsertUser, shared_hashmap: &Arc<Mutex<HashMap<String, User>>>) -> Option<User> {
-    let mut acquired_map = shared_hashmap.lock().unwrap_or_else(|_| {
-        println!("Error unwrapping Option!");
-        panic!("Mutex lock failed");
-    });
-
-    match acquired_map.get(email) {
-        Some(user) => {
-            let updated_user = User {
-                id: user.id,
-                email: user.email.clone(),
-                password: request.password,
-                fullname: request.fullname,
-                role: request.role,
-            };
-            acquired_map.insert(email.clone(), updated_user.clone());
-            Some(updated_user)
+#[tracing::instrument(skip_all, fields(email = %redact_email(&request.email)))]
+pub async fn create_user(request: UpsertUser, shared_hashmap: &UserStore) -> Result<User, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["create_user"]).start_timer();
+
+    match shared_hashmap.repository.create(request).await {
+        Ok(new_user) => {
+            tracing::debug!(id = new_user.id, "user created");
+            Ok(new_user)
         }
-        None => None,
+        Err(RepositoryError::Duplicate) => {
+            tracing::debug!("user already exists");
+            Err(RepositoryError::Duplicate)
+        }
+        Err(error) => Err(error),
     }
 }
 
-pub async fn delete_user_by_email(email: &String, shared_hashmap: &Arc<Mutex<HashMap<String, User>>>) -> Option<User> {
-    let mut acquired_map = shared_hashmap.lock().unwrap_or_else(|_| {
-        println!("Error unwrapping Option!");
-        panic!("Mutex lock failed");
-    });
+// Outcome of one user passed to create_users
+#[derive(Debug, Clone)]
+pub enum BatchOutcome {
+    Created(User),
+    Duplicate,
+    // Could have been created, but another user of an all-or-nothing batch could not
+    Skipped,
+}
 
-    match acquired_map.get(email) {
-        Some(_user) => {
-            acquired_map.remove(email)
-        }
-        None => None
+// Creates the users, as one transaction where the backend has them. An email repeated within the batch is a
+// duplicate of its first occurrence. With all_or_nothing, nothing is stored unless every user can be created
+#[tracing::instrument(skip_all, fields(count = requests.len(), all_or_nothing))]
+pub async fn create_users(requests: Vec<UpsertUser>, all_or_nothing: bool, shared_hashmap: &UserStore) -> Result<Vec<BatchOutcome>, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["create_users"]).start_timer();
+
+    let outcomes = shared_hashmap.repository.create_many(requests, all_or_nothing).await?;
+    if all_or_nothing && !outcomes.iter().all(|outcome| matches!(outcome, BatchOutcome::Created(_))) {
+        tracing::debug!("batch rolled back");
     }
+    Ok(outcomes)
+}
+
+#[tracing::instrument(skip_all, fields(email = %redact_email(email)))]
+pub async fn get_user_by_email(email: &str, shared_hashmap: &UserStore) -> Result<Option<User>, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["get_user_by_email"]).start_timer();
+    shared_hashmap.repository.get(email).await
+}
+
+#[tracing::instrument(skip_all, fields(email = %redact_email(email)))]
+pub async fn update_user_by_email(email: &str, request: UpsertUser, shared_hashmap: &UserStore) -> Result<Option<User>, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["update_user_by_email"]).start_timer();
+    shared_hashmap.repository.update(email, request).await
+}
+
+#[tracing::instrument(skip_all, fields(email = %redact_email(email)))]
+pub async fn delete_user_by_email(email: &str, shared_hashmap: &UserStore) -> Result<Option<User>, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["delete_user_by_email"]).start_timer();
+    shared_hashmap.repository.delete(email).await
+}
+
+// One page of users, continuing from the cursor of the previous page. Paging by key rather than by offset keeps
+// pages consistent while users are created or deleted between them
+#[tracing::instrument(skip_all, fields(limit))]
+pub async fn get_users_page(cursor: Option<&PageCursor>, limit: usize, shared_hashmap: &UserStore) -> Result<UserPage, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["get_users_page"]).start_timer();
+    shared_hashmap.repository.list_page(cursor, limit).await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn get_all_users(shared_hashmap: &UserStore) -> Result<Vec<User>, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["get_all_users"]).start_timer();
+    shared_hashmap.repository.list_all().await
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn count_users(shared_hashmap: &UserStore) -> Result<Option<usize>, RepositoryError> {
+    let _timer = STORE_OPERATION_DURATION.with_label_values(&["count_users"]).start_timer();
+    shared_hashmap.repository.count().await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn create_test_hashmap() -> Arc<Mutex<HashMap<String, User>>> {
-        Arc::new(Mutex::new(HashMap::new()))
+    fn create_test_hashmap() -> UserStore {
+        UserStore::new()
     }
 
     fn create_test_upsert_user(email: &str) -> UpsertUser {
@@ -91,7 +152,7 @@ mod tests {
 
         let result = create_user(request, &hashmap).await;
 
-        assert!(result.is_some());
+        assert!(result.is_ok());
         let user = result.unwrap();
         assert_eq!(user.email, "jerry@seinfeld.com");
         assert_eq!(user.password, "these_pretzels_are_making_me_thirsty");
@@ -107,10 +168,10 @@ mod tests {
         let request2 = create_test_upsert_user("george@yankees.com");
 
         let result1 = create_user(request1, &hashmap).await;
-        assert!(result1.is_some());
+        assert!(result1.is_ok());
 
         let result2 = create_user(request2, &hashmap).await;
-        assert!(result2.is_none());
+        assert!(matches!(result2, Err(RepositoryError::Duplicate)));
     }
 
     #[tokio::test]
@@ -121,23 +182,61 @@ mod tests {
         let user2 = create_user(create_test_upsert_user("kramer@apartments5b.com"), &hashmap).await;
         let user3 = create_user(create_test_upsert_user("newman@apartments5e.com"), &hashmap).await;
 
-        assert!(user1.is_some());
-        assert!(user2.is_some());
-        assert!(user3.is_some());
+        assert!(user1.is_ok());
+        assert!(user2.is_ok());
+        assert!(user3.is_ok());
 
         assert_eq!(user1.unwrap().id, 1);
         assert_eq!(user2.unwrap().id, 2);
         assert_eq!(user3.unwrap().id, 3);
     }
 
+    #[tokio::test]
+    async fn test_create_users_best_effort() {
+        let hashmap = create_test_hashmap();
+        create_user(create_test_upsert_user("jerry@seinfeld.com"), &hashmap).await.unwrap();
+
+        let requests = vec![
+            create_test_upsert_user("elaine@benes.com"),
+            create_test_upsert_user("jerry@seinfeld.com"),
+            create_test_upsert_user("george@costanza.com"),
+            create_test_upsert_user("elaine@benes.com"),
+        ];
+        let outcomes = create_users(requests, false, &hashmap).await.unwrap();
+
+        assert!(matches!(&outcomes[0], BatchOutcome::Created(user) if user.id == 2));
+        assert!(matches!(outcomes[1], BatchOutcome::Duplicate));
+        assert!(matches!(&outcomes[2], BatchOutcome::Created(user) if user.id == 3));
+        assert!(matches!(outcomes[3], BatchOutcome::Duplicate));
+        assert_eq!(count_users(&hashmap).await.unwrap(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_create_users_all_or_nothing() {
+        let hashmap = create_test_hashmap();
+        create_user(create_test_upsert_user("jerry@seinfeld.com"), &hashmap).await.unwrap();
+
+        let requests = vec![create_test_upsert_user("elaine@benes.com"), create_test_upsert_user("jerry@seinfeld.com")];
+        let outcomes = create_users(requests, true, &hashmap).await.unwrap();
+
+        assert!(matches!(outcomes[..], [BatchOutcome::Skipped, BatchOutcome::Duplicate]));
+        assert_eq!(count_users(&hashmap).await.unwrap(), Some(1));
+
+        let requests = vec![create_test_upsert_user("elaine@benes.com"), create_test_upsert_user("george@costanza.com")];
+        let outcomes = create_users(requests, true, &hashmap).await.unwrap();
+
+        assert!(outcomes.iter().all(|outcome| matches!(outcome, BatchOutcome::Created(_))));
+        assert_eq!(count_users(&hashmap).await.unwrap(), Some(3));
+    }
+
     #[tokio::test]
     async fn test_get_user_by_email_success() {
         let hashmap = create_test_hashmap();
         let request = create_test_upsert_user("elaine@pendant_publishing.com");
 
-        create_user(request, &hashmap).await;
+        create_user(request, &hashmap).await.unwrap();
 
-        let result = get_user_by_email(&"elaine@pendant_publishing.com".to_string(), &hashmap).await;
+        let result = get_user_by_email("elaine@pendant_publishing.com", &hashmap).await.unwrap();
 
         assert!(result.is_some());
         let user = result.unwrap();
@@ -148,7 +247,7 @@ mod tests {
     async fn test_get_user_by_email_not_found() {
         let hashmap = create_test_hashmap();
 
-        let result = get_user_by_email(&"larry_david@curb.com".to_string(), &hashmap).await;
+        let result = get_user_by_email("larry_david@curb.com", &hashmap).await.unwrap();
 
         assert!(result.is_none());
     }
@@ -158,7 +257,7 @@ mod tests {
         let hashmap = create_test_hashmap();
         let request = create_test_upsert_user("puddy@devils.com");
 
-        create_user(request, &hashmap).await;
+        create_user(request, &hashmap).await.unwrap();
 
         let update_request = UpsertUser {
             email: "puddy@devils.com".to_string(),
@@ -167,7 +266,7 @@ mod tests {
             role: "car_salesman".to_string(),
         };
 
-        let result = update_user_by_email(&"puddy@devils.com".to_string(), update_request, &hashmap).await;
+        let result = update_user_by_email("puddy@devils.com", update_request, &hashmap).await.unwrap();
 
         assert!(result.is_some());
         let updated_user = result.unwrap();
@@ -188,7 +287,7 @@ mod tests {
             role: "restaurant_owner".to_string(),
         };
 
-        let result = update_user_by_email(&"babu@dreamcafe.com".to_string(), update_request, &hashmap).await;
+        let result = update_user_by_email("babu@dreamcafe.com", update_request, &hashmap).await.unwrap();
 
         assert!(result.is_none());
     }
@@ -198,16 +297,16 @@ mod tests {
         let hashmap = create_test_hashmap();
         let request = create_test_upsert_user("crazy_joe_davola@opera.com");
 
-        create_user(request, &hashmap).await;
+        create_user(request, &hashmap).await.unwrap();
 
-        let result = delete_user_by_email(&"crazy_joe_davola@opera.com".to_string(), &hashmap).await;
+        let result = delete_user_by_email("crazy_joe_davola@opera.com", &hashmap).await.unwrap();
 
         assert!(result.is_some());
         let deleted_user = result.unwrap();
         assert_eq!(deleted_user.email, "crazy_joe_davola@opera.com");
 
         // Verify user is actually deleted
-        let get_result = get_user_by_email(&"crazy_joe_davola@opera.com".to_string(), &hashmap).await;
+        let get_result = get_user_by_email("crazy_joe_davola@opera.com", &hashmap).await.unwrap();
         assert!(get_result.is_none());
     }
 
@@ -215,7 +314,7 @@ mod tests {
     async fn test_delete_user_by_email_not_found() {
         let hashmap = create_test_hashmap();
 
-        let result = delete_user_by_email(&"bob_sacamano@urban_legend.com".to_string(), &hashmap).await;
+        let result = delete_user_by_email("bob_sacamano@urban_legend.com", &hashmap).await.unwrap();
 
         assert!(result.is_none());
     }
@@ -225,9 +324,9 @@ mod tests {
         let hashmap = create_test_hashmap();
 
         // Create multiple users concurrently
-        let hashmap1 = Arc::clone(&hashmap);
-        let hashmap2 = Arc::clone(&hashmap);
-        let hashmap3 = Arc::clone(&hashmap);
+        let hashmap1 = hashmap.clone();
+        let hashmap2 = hashmap.clone();
+        let hashmap3 = hashmap.clone();
 
         let handle1 = tokio::spawn(async move {
             create_user(create_test_upsert_user("helen@seinfeld.com"), &hashmap1).await
@@ -243,13 +342,62 @@ mod tests {
 
         let results = tokio::join!(handle1, handle2, handle3);
 
-        assert!(results.0.unwrap().is_some());
-        assert!(results.1.unwrap().is_some());
-        assert!(results.2.unwrap().is_some());
+        assert!(results.0.unwrap().is_ok());
+        assert!(results.1.unwrap().is_ok());
+        assert!(results.2.unwrap().is_ok());
 
         // Verify all users were created
-        let map = hashmap.lock().unwrap();
-        assert_eq!(map.len(), 3);
+        assert_eq!(count_users(&hashmap).await.unwrap(), Some(3));
     }
-}
 
+    #[tokio::test]
+    async fn test_reads_do_not_serialize() {
+        let hashmap = create_test_hashmap();
+        create_user(create_test_upsert_user("mickey@abbott.com"), &hashmap).await.unwrap();
+
+        // Holding a read guard must not block other readers
+        let _guard = hashmap.memory().unwrap().read().await;
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            get_user_by_email("mickey@abbott.com", &hashmap),
+        ).await;
+
+        assert!(result.expect("read blocked by concurrent reader").unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_users_page_pages_by_email() {
+        let hashmap = create_test_hashmap();
+        for email in ["uncle@leo.com", "aunt@baby.com", "jerry@seinfeld.com", "george@costanza.com", "elaine@benes.com"] {
+            create_user(create_test_upsert_user(email), &hashmap).await.unwrap();
+        }
+
+        let page = get_users_page(None, 2, &hashmap).await.unwrap();
+        let emails: Vec<&str> = page.users.iter().map(|user| user.email.as_str()).collect();
+        assert_eq!(emails, ["aunt@baby.com", "elaine@benes.com"]);
+
+        let page = get_users_page(page.next.as_ref(), 2, &hashmap).await.unwrap();
+        let emails: Vec<&str> = page.users.iter().map(|user| user.email.as_str()).collect();
+        assert_eq!(emails, ["george@costanza.com", "jerry@seinfeld.com"]);
+
+        let page = get_users_page(page.next.as_ref(), 2, &hashmap).await.unwrap();
+        assert_eq!(page.users.len(), 1);
+        assert_eq!(page.next, None);
+        assert!(get_users_page(Some(&PageCursor("uncle@leo.com".to_string())), 2, &hashmap).await.unwrap().users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_all_users_ordered_by_id() {
+        let hashmap = create_test_hashmap();
+
+        create_user(create_test_upsert_user("tim@whatley.com"), &hashmap).await.unwrap();
+        create_user(create_test_upsert_user("jack@klompus.com"), &hashmap).await.unwrap();
+        create_user(create_test_upsert_user("j@peterman.com"), &hashmap).await.unwrap();
+
+        let users = get_all_users(&hashmap).await.unwrap();
+
+        let ids: Vec<i32> = users.iter().map(|user| user.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(users[1].email, "jack@klompus.com");
+    }
+}
@@ -0,0 +1,140 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use serde_json::{json, Value};
+use hvalfangst_rust_crud_with_axum::{
+    api::api_routes,
+    config::ApiConfig,
+    users::{router::{rewrite_batch_alias, users_routes}, service::UserStore},
+};
+
+fn create_test_app() -> axum::Router {
+    let hashmap = UserStore::new();
+    api_routes(&ApiConfig::default(), [("v1", users_routes(hashmap))])
+}
+
+fn user(email: &str, fullname: &str) -> Value {
+    json!({"email": email, "password": "serenity_now", "fullname": fullname, "role": "customer"})
+}
+
+async fn send(app: axum::Router, method: &str, uri: &str, content_type: &str, body: String) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .unwrap();
+
+    // As the server does before routing
+    let response = app.oneshot(rewrite_batch_alias(request)).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn post_batch(app: axum::Router, uri: &str, content_type: &str, body: String) -> (StatusCode, Value) {
+    send(app, "POST", uri, content_type, body).await
+}
+
+async fn get_user(app: axum::Router, email: &str) -> StatusCode {
+    send(app, "GET", &format!("/v1/users/{}", email), "application/json", String::new()).await.0
+}
+
+#[tokio::test]
+async fn test_json_array_is_created() {
+    let app = create_test_app();
+    let body = json!([user("frank@costanza.com", "Frank Costanza"), user("estelle@costanza.com", "Estelle Costanza")]);
+
+    let (status, report) = post_batch(app.clone(), "/v1/users/batch", "application/json", body.to_string()).await;
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(report["mode"], "all_or_nothing");
+    assert_eq!(report["created"], 2);
+    assert_eq!(report["results"][1], json!({"index": 1, "status": "created", "email": "estelle@costanza.com", "id": 2}));
+    assert_eq!(get_user(app, "estelle@costanza.com").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_all_or_nothing_creates_nothing_on_failure() {
+    let app = create_test_app();
+    post_batch(app.clone(), "/users/batch", "application/json", json!([user("frank@costanza.com", "Frank")]).to_string()).await;
+
+    let body = json!([user("lloyd@braun.com", "Lloyd Braun"), user("frank@costanza.com", "Frank Costanza")]);
+    let (status, report) = post_batch(app.clone(), "/users:batch", "application/json", body.to_string()).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(report["created"], 0);
+    assert_eq!(report["results"][0]["status"], "skipped");
+    assert_eq!(report["results"][1]["status"], "duplicate");
+    assert_eq!(get_user(app.clone(), "lloyd@braun.com").await, StatusCode::NOT_FOUND);
+
+    // An invalid item fails the batch before duplicates are looked for
+    let body = json!([user("lloyd@braun.com", "Lloyd Braun"), {"email": "lloyd@braun.com"}]);
+    let (status, report) = post_batch(app.clone(), "/users:batch", "application/json", body.to_string()).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(report["invalid"], 1);
+    assert_eq!(report["results"][1]["field"], "password");
+    assert_eq!(get_user(app, "lloyd@braun.com").await, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_best_effort_ndjson_reports_each_item() {
+    let app = create_test_app();
+    let lines = [
+        user("mr@pitt.com", "Mr. Pitt").to_string(),
+        user("not an email", "Bob Sacamano").to_string(),
+        user("mr@pitt.com", "Justin Pitt").to_string(),
+        "{\"email\": ".to_string(),
+        user("kenny@bania.com", "Kenny Bania").to_string(),
+    ];
+
+    let (status, report) = post_batch(app.clone(), "/v1/users:batch?mode=best_effort", "application/x-ndjson", lines.join("\n")).await;
+
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    assert_eq!(report["mode"], "best_effort");
+    assert_eq!((report["created"].as_u64(), report["duplicates"].as_u64(), report["invalid"].as_u64()), (Some(2), Some(1), Some(2)));
+
+    let statuses: Vec<&str> = report["results"].as_array().unwrap().iter().map(|result| result["status"].as_str().unwrap()).collect();
+    assert_eq!(statuses, ["created", "invalid", "duplicate", "invalid", "created"]);
+    assert_eq!(report["results"][1]["field"], "email");
+    assert_eq!(report["results"][4]["id"], 2);
+    assert_eq!(get_user(app, "kenny@bania.com").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_rejected_bodies() {
+    let app = create_test_app();
+    let body = json!([user("sue.ellen@mischke.com", "Sue Ellen Mischke")]).to_string();
+
+    let (status, _) = post_batch(app.clone(), "/users:batch", "text/csv", body.clone()).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let (status, _) = post_batch(app.clone(), "/users:batch?mode=most_of_it", "application/json", body.clone()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, error) = post_batch(app.clone(), "/users:batch", "application/json", user("sue.ellen@mischke.com", "Sue Ellen").to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error["error"].as_str().unwrap().contains("JSON array"));
+
+}
+
+#[tokio::test]
+async fn test_only_the_exact_alias_reaches_batches() {
+    let app = create_test_app();
+    let body = json!([user("sue.ellen@mischke.com", "Sue Ellen Mischke")]).to_string();
+
+    for uri in ["/users:bulk", "/usersXYZ", "/v1/users:batchXYZ", "/v2/users:batch"] {
+        let (status, _) = post_batch(app.clone(), uri, "application/json", body.clone()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+    }
+    let (status, _) = send(app.clone(), "GET", "/usersXYZ", "application/json", String::new()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(app.clone(), "GET", "/v1/users:batch", "application/json", String::new()).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+
+    let (status, _) = post_batch(app, "/v1/users:batch", "application/json", body).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
@@ -0,0 +1,229 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc
+    },
+    time::Duration
+};
+use async_trait::async_trait;
+use hvalfangst_rust_crud_with_axum::{
+    config::CacheConfig,
+    metrics::USER_CACHE_REQUESTS_TOTAL,
+    storage::{
+        cache::{CachedProfile, CachedRepository, CachedUser, LruUserCache, RedisUserCache, UserCache},
+        memory::MemoryRepository,
+        PageCursor, RepositoryError, UserPage, UserRepository
+    },
+    users::{
+        model::{User, UpsertUser},
+        service::{create_user, delete_user_by_email, get_user_by_email, update_user_by_email, BatchOutcome, UserStore}
+    },
+};
+
+// A memory repository that counts the reads reaching it. With fold_case, it finds users by their lowercased
+// email, as the Postgres backend does
+#[derive(Default)]
+struct CountingRepository {
+    users: MemoryRepository,
+    gets: AtomicUsize,
+    fold_case: bool,
+}
+
+#[async_trait]
+impl UserRepository for CountingRepository {
+    async fn create(&self, mut request: UpsertUser) -> Result<User, RepositoryError> {
+        request.email = self.normalize_email(&request.email);
+        self.users.create(request).await
+    }
+
+    async fn create_many(&self, requests: Vec<UpsertUser>, all_or_nothing: bool) -> Result<Vec<BatchOutcome>, RepositoryError> {
+        self.users.create_many(requests, all_or_nothing).await
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        self.gets.fetch_add(1, Ordering::SeqCst);
+        self.users.get(&self.normalize_email(email)).await
+    }
+
+    async fn update(&self, email: &str, request: UpsertUser) -> Result<Option<User>, RepositoryError> {
+        self.users.update(&self.normalize_email(email), request).await
+    }
+
+    async fn delete(&self, email: &str) -> Result<Option<User>, RepositoryError> {
+        self.users.delete(&self.normalize_email(email)).await
+    }
+
+    async fn list_page(&self, cursor: Option<&PageCursor>, limit: usize) -> Result<UserPage, RepositoryError> {
+        self.users.list_page(cursor, limit).await
+    }
+
+    async fn list_all(&self) -> Result<Vec<User>, RepositoryError> {
+        self.users.list_all().await
+    }
+
+    async fn count(&self) -> Result<Option<usize>, RepositoryError> {
+        self.users.count().await
+    }
+
+    async fn ping(&self) -> Result<(), RepositoryError> {
+        self.users.ping().await
+    }
+
+    fn normalize_email(&self, email: &str) -> String {
+        if self.fold_case { email.to_lowercase() } else { email.to_string() }
+    }
+}
+
+fn cached_store(config: &CacheConfig) -> (UserStore, Arc<CountingRepository>) {
+    cached_store_over(CountingRepository::default(), config)
+}
+
+fn cached_store_over(backend: CountingRepository, config: &CacheConfig) -> (UserStore, Arc<CountingRepository>) {
+    let backend = Arc::new(backend);
+    let cache = Arc::new(LruUserCache::new(config.capacity));
+    let store = UserStore::from_repository(Arc::new(CachedRepository::new(backend.clone(), cache, config)));
+    (store, backend)
+}
+
+fn request(email: &str, role: &str) -> UpsertUser {
+    UpsertUser {
+        email: email.to_string(),
+        password: "yada_yada".to_string(),
+        fullname: "Elaine Benes".to_string(),
+        role: role.to_string(),
+    }
+}
+
+fn profile(email: &str) -> CachedProfile {
+    CachedProfile { id: 1, email: email.to_string(), fullname: "Elaine Benes".to_string(), role: "editor".to_string() }
+}
+
+#[tokio::test]
+async fn test_repeated_reads_are_served_from_cache() {
+    let (store, backend) = cached_store(&CacheConfig::default());
+    create_user(request("elaine@pendant.com", "editor"), &store).await.unwrap();
+    let hits = USER_CACHE_REQUESTS_TOTAL.with_label_values(&["hit"]).get();
+
+    for _ in 0..3 {
+        assert_eq!(get_user_by_email("elaine@pendant.com", &store).await.unwrap().unwrap().role, "editor");
+    }
+
+    assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+    assert!(USER_CACHE_REQUESTS_TOTAL.with_label_values(&["hit"]).get() >= hits + 2);
+}
+
+#[tokio::test]
+async fn test_passwords_are_not_cached() {
+    let (store, _backend) = cached_store(&CacheConfig::default());
+    create_user(request("elaine@pendant.com", "editor"), &store).await.unwrap();
+
+    // A miss answers the same as the hit after it
+    for _ in 0..2 {
+        let user = get_user_by_email("elaine@pendant.com", &store).await.unwrap().unwrap();
+        assert_eq!((user.role.as_str(), user.password.as_str()), ("editor", ""));
+    }
+
+    let stored = serde_json::to_value(CachedUser::Found { user: profile("elaine@pendant.com") }).unwrap();
+    assert!(stored["user"].get("password").is_none());
+}
+
+#[tokio::test]
+async fn test_missing_user_is_remembered_until_created() {
+    let (store, backend) = cached_store(&CacheConfig::default());
+
+    assert!(get_user_by_email("elaine@jpeterman.com", &store).await.unwrap().is_none());
+    assert!(get_user_by_email("elaine@jpeterman.com", &store).await.unwrap().is_none());
+    assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+
+    create_user(request("elaine@jpeterman.com", "catalog_writer"), &store).await.unwrap();
+    assert!(get_user_by_email("elaine@jpeterman.com", &store).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_writes_drop_cached_user() {
+    let (store, _backend) = cached_store(&CacheConfig::default());
+    create_user(request("elaine@pendant.com", "editor"), &store).await.unwrap();
+    get_user_by_email("elaine@pendant.com", &store).await.unwrap();
+
+    update_user_by_email("elaine@pendant.com", request("elaine@pendant.com", "president"), &store).await.unwrap();
+    assert_eq!(get_user_by_email("elaine@pendant.com", &store).await.unwrap().unwrap().role, "president");
+
+    delete_user_by_email("elaine@pendant.com", &store).await.unwrap();
+    assert!(get_user_by_email("elaine@pendant.com", &store).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_emails_the_backend_treats_alike_share_an_entry() {
+    let backend = CountingRepository { fold_case: true, ..CountingRepository::default() };
+    let (store, backend) = cached_store_over(backend, &CacheConfig::default());
+    create_user(request("elaine@pendant.com", "editor"), &store).await.unwrap();
+
+    get_user_by_email("Elaine@Pendant.com", &store).await.unwrap();
+    get_user_by_email("ELAINE@pendant.com", &store).await.unwrap();
+    assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+
+    update_user_by_email("elaine@PENDANT.com", request("elaine@pendant.com", "president"), &store).await.unwrap();
+    assert_eq!(get_user_by_email("Elaine@Pendant.com", &store).await.unwrap().unwrap().role, "president");
+
+    delete_user_by_email("ELAINE@PENDANT.COM", &store).await.unwrap();
+    assert!(get_user_by_email("Elaine@Pendant.com", &store).await.unwrap().is_none());
+
+    create_user(request("Elaine@Pendant.com", "editor"), &store).await.unwrap();
+    assert!(get_user_by_email("elaine@pendant.com", &store).await.unwrap().is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_entries_expire_after_their_ttl() {
+    let config = CacheConfig { ttl_secs: 60, negative_ttl_secs: 0, ..CacheConfig::default() };
+    let (store, backend) = cached_store(&config);
+    create_user(request("elaine@pendant.com", "editor"), &store).await.unwrap();
+
+    get_user_by_email("elaine@pendant.com", &store).await.unwrap();
+    tokio::time::advance(Duration::from_secs(59)).await;
+    get_user_by_email("elaine@pendant.com", &store).await.unwrap();
+    assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+
+    tokio::time::advance(Duration::from_secs(2)).await;
+    get_user_by_email("elaine@pendant.com", &store).await.unwrap();
+    assert_eq!(backend.gets.load(Ordering::SeqCst), 2);
+
+    // Without a negative ttl, a missing user is never remembered
+    get_user_by_email("puddy@saab.com", &store).await.unwrap();
+    get_user_by_email("puddy@saab.com", &store).await.unwrap();
+    assert_eq!(backend.gets.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn test_least_recently_used_is_evicted() {
+    let cache = LruUserCache::new(2);
+    let ttl = Duration::from_secs(60);
+    cache.put("elaine@pendant.com", CachedUser::Found { user: profile("elaine@pendant.com") }, ttl).await.unwrap();
+    cache.put("puddy@saab.com", CachedUser::Missing, ttl).await.unwrap();
+
+    cache.get("elaine@pendant.com").await.unwrap();
+    cache.put("jake@jarmel.com", CachedUser::Missing, ttl).await.unwrap();
+
+    assert!(cache.get("elaine@pendant.com").await.unwrap().is_some());
+    assert!(cache.get("puddy@saab.com").await.unwrap().is_none());
+    assert!(cache.get("jake@jarmel.com").await.unwrap().is_some());
+}
+
+// Needs a Redis, e.g. TEST_REDIS_URL=redis://localhost:6379, and is skipped without one
+#[tokio::test]
+async fn test_redis_cache_keeps_and_drops_users() {
+    let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+        eprintln!("TEST_REDIS_URL is not set, skipping");
+        return;
+    };
+    let cache = RedisUserCache::connect(&url, &format!("cache-tests-{}:", std::process::id())).await.unwrap();
+    let ttl = Duration::from_secs(60);
+
+    cache.put("elaine@pendant.com", CachedUser::Found { user: profile("elaine@pendant.com") }, ttl).await.unwrap();
+    cache.put("puddy@saab.com", CachedUser::Missing, ttl).await.unwrap();
+    assert!(matches!(cache.get("elaine@pendant.com").await.unwrap(), Some(CachedUser::Found { user }) if user.role == "editor"));
+    assert!(matches!(cache.get("puddy@saab.com").await.unwrap(), Some(CachedUser::Missing)));
+
+    cache.invalidate("elaine@pendant.com").await.unwrap();
+    assert!(cache.get("elaine@pendant.com").await.unwrap().is_none());
+    cache.invalidate("puddy@saab.com").await.unwrap();
+}
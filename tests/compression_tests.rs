@@ -0,0 +1,192 @@
+use std::io::{Read, Write};
+use axum::{
+    body::Body,
+    extract::DefaultBodyLimit,
+    http::{Request, StatusCode},
+    response::Response,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use tower::ServiceExt;
+use serde_json::{json, Value};
+use hvalfangst_rust_crud_with_axum::{
+    compression::compressed,
+    config::{CompressionAlgorithm, CompressionConfig},
+    users::{router::users_routes, service::UserStore},
+};
+
+const MAX_BODY_BYTES: usize = 1024;
+
+// Jackie Chiles is stored up front so GET responses have a body worth compressing
+async fn create_test_app(config: CompressionConfig) -> axum::Router {
+    let hashmap = UserStore::new();
+    let app = compressed(users_routes(hashmap), &config).layer(DefaultBodyLimit::max(MAX_BODY_BYTES));
+
+    let response = app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/users")
+                .header("content-type", "application/json")
+                .body(Body::from(jackie().to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    app
+}
+
+fn low_threshold() -> CompressionConfig {
+    CompressionConfig { min_size_bytes: 32, ..CompressionConfig::default() }
+}
+
+fn jackie() -> Value {
+    json!({
+        "email": "jackie@chiles.com",
+        "password": "outrageous_egregious_preposterous",
+        "fullname": "Jackie Chiles",
+        "role": "lawyer"
+    })
+}
+
+async fn get_jackie(app: axum::Router, accept_encoding: &str) -> Response {
+    app.oneshot(
+        Request::builder()
+            .method("GET")
+            .uri("/users/jackie@chiles.com")
+            .header("accept-encoding", accept_encoding)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn body_bytes(response: Response) -> Vec<u8> {
+    hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec()
+}
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn test_responses_are_compressed_as_negotiated() {
+    let app = create_test_app(low_threshold()).await;
+
+    let response = get_jackie(app.clone(), "gzip").await;
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    let mut json = String::new();
+    GzDecoder::new(body_bytes(response).await.as_slice()).read_to_string(&mut json).unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&json).unwrap()["fullname"], "Jackie Chiles");
+
+    let response = get_jackie(app.clone(), "br").await;
+    assert_eq!(response.headers()["content-encoding"], "br");
+    let mut json = Vec::new();
+    brotli::Decompressor::new(body_bytes(response).await.as_slice(), 4096).read_to_end(&mut json).unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&json).unwrap()["fullname"], "Jackie Chiles");
+
+    let response = get_jackie(app.clone(), "zstd").await;
+    assert_eq!(response.headers()["content-encoding"], "zstd");
+    let json = zstd::decode_all(body_bytes(response).await.as_slice()).unwrap();
+    assert_eq!(serde_json::from_slice::<Value>(&json).unwrap()["fullname"], "Jackie Chiles");
+
+    let response = get_jackie(app, "identity").await;
+    assert!(!response.headers().contains_key("content-encoding"));
+}
+
+#[tokio::test]
+async fn test_small_responses_are_not_compressed() {
+    let app = create_test_app(CompressionConfig::default()).await;
+
+    let response = get_jackie(app, "gzip, br, zstd").await;
+
+    assert!(!response.headers().contains_key("content-encoding"));
+    let user: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(user["email"], "jackie@chiles.com");
+}
+
+#[tokio::test]
+async fn test_disabled_algorithms_are_not_offered() {
+    let config = CompressionConfig { algorithms: vec![CompressionAlgorithm::Gzip], ..low_threshold() };
+    let app = create_test_app(config).await;
+
+    let response = get_jackie(app, "br;q=1.0, gzip;q=0.5").await;
+
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+}
+
+#[tokio::test]
+async fn test_compressed_request_bodies_are_decompressed() {
+    let app = create_test_app(low_threshold()).await;
+    let body = json!({
+        "email": "jackie@chiles.com",
+        "password": "outrageous_egregious_preposterous",
+        "fullname": "Jackie Chiles",
+        "role": "attorney"
+    });
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/users/jackie@chiles.com")
+                .header("content-type", "application/json")
+                .header("content-encoding", "gzip")
+                .body(Body::from(gzip(body.to_string().as_bytes())))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let user: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(user["role"], "attorney");
+}
+
+#[tokio::test]
+async fn test_body_limit_applies_after_decompression() {
+    let app = create_test_app(low_threshold()).await;
+    let mut body = jackie();
+    body["fullname"] = Value::String("a".repeat(MAX_BODY_BYTES * 10));
+    let compressed_body = gzip(body.to_string().as_bytes());
+    assert!(compressed_body.len() < MAX_BODY_BYTES);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri("/users/jackie@chiles.com")
+                .header("content-type", "application/json")
+                .header("content-encoding", "gzip")
+                .body(Body::from(compressed_body))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_unsupported_content_encoding_is_rejected() {
+    let app = create_test_app(low_threshold()).await;
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/users")
+                .header("content-type", "application/json")
+                .header("content-encoding", "compress")
+                .body(Body::from(jackie().to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
@@ -0,0 +1,113 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+};
+use tower::ServiceExt;
+use hvalfangst_rust_crud_with_axum::{
+    config::CorsConfig,
+    cors::cors_layer,
+    users::{router::users_routes, service::UserStore},
+};
+
+fn create_test_app(config: &CorsConfig) -> axum::Router {
+    let hashmap = UserStore::new();
+    users_routes(hashmap).layer(cors_layer(config))
+}
+
+fn admin_spa_config() -> CorsConfig {
+    CorsConfig {
+        allowed_origins: vec!["https://admin.vandelay.com".to_string()],
+        allowed_origin_regex: Some(r"https://[a-z0-9-]+\.kramerica\.com".to_string()),
+        allow_credentials: true,
+        ..CorsConfig::default()
+    }
+}
+
+async fn preflight(app: axum::Router, origin: &str) -> Response {
+    app.oneshot(
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/users/elaine@benes.com")
+            .header("origin", origin)
+            .header("access-control-request-method", "PUT")
+            .header("access-control-request-headers", "content-type")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn get_from(app: axum::Router, origin: &str) -> Response {
+    app.oneshot(
+        Request::builder()
+            .method("GET")
+            .uri("/users/elaine@benes.com")
+            .header("origin", origin)
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_preflight_from_allowed_origin() {
+    let app = create_test_app(&admin_spa_config());
+
+    for origin in ["https://admin.vandelay.com", "https://staging-1.kramerica.com"] {
+        let response = preflight(app.clone(), origin).await;
+        let headers = response.headers();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(headers["access-control-allow-origin"], origin);
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-max-age"], "600");
+        assert!(headers["access-control-allow-methods"].to_str().unwrap().contains("PUT"));
+        assert_eq!(headers["access-control-allow-headers"], "content-type");
+    }
+}
+
+#[tokio::test]
+async fn test_preflight_from_rejected_origin() {
+    let app = create_test_app(&admin_spa_config());
+
+    // The regex has to match the whole origin, not a prefix of it
+    for origin in ["https://evil.com", "https://staging.kramerica.com.evil.com", "http://admin.vandelay.com"] {
+        let response = preflight(app.clone(), origin).await;
+
+        assert!(!response.headers().contains_key("access-control-allow-origin"), "{} was allowed", origin);
+    }
+}
+
+#[tokio::test]
+async fn test_simple_request_headers() {
+    let app = create_test_app(&admin_spa_config());
+
+    let response = get_from(app.clone(), "https://admin.vandelay.com").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["access-control-allow-origin"], "https://admin.vandelay.com");
+    assert!(response.headers()["access-control-expose-headers"].to_str().unwrap().contains("x-request-id"));
+
+    // The request is still handled, the browser is what withholds the response from the page
+    let response = get_from(app, "https://evil.com").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn test_wildcard_origin() {
+    let config = CorsConfig { allowed_origins: vec!["*".to_string()], ..CorsConfig::default() };
+
+    let response = get_from(create_test_app(&config), "https://anyone.com").await;
+
+    assert_eq!(response.headers()["access-control-allow-origin"], "*");
+}
+
+#[tokio::test]
+async fn test_no_origins_allowed_by_default() {
+    let response = preflight(create_test_app(&CorsConfig::default()), "https://admin.vandelay.com").await;
+
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+}
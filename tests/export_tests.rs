@@ -0,0 +1,109 @@
+use axum::{
+    body::{Body, HttpBody},
+    http::{Request, StatusCode},
+    response::Response,
+};
+use tower::ServiceExt;
+use serde_json::Value;
+use hvalfangst_rust_crud_with_axum::users::{
+    export::EXPORT_PAGE_SIZE,
+    model::UpsertUser,
+    router::users_routes,
+    service::{create_user, UserStore}
+};
+
+async fn create_test_app(count: usize) -> axum::Router {
+    let hashmap = UserStore::new();
+    for index in 0..count {
+        let request = UpsertUser {
+            email: format!("clerk{:04}@vandelay.com", index),
+            password: "importer_exporter".to_string(),
+            fullname: format!("Clerk, No. {}", index),
+            role: "latex".to_string(),
+        };
+        create_user(request, &hashmap).await.unwrap();
+    }
+    users_routes(hashmap)
+}
+
+async fn export(app: axum::Router, uri: &str, accept: Option<&str>) -> Response {
+    let mut builder = Request::builder().uri(uri);
+    if let Some(accept) = accept {
+        builder = builder.header("accept", accept);
+    }
+    app.oneshot(builder.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn body_text(response: Response) -> String {
+    String::from_utf8(hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_ndjson_is_streamed_in_pages_without_passwords() {
+    let app = create_test_app(EXPORT_PAGE_SIZE + 3).await;
+
+    let response = export(app, "/users/export", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let mut body = response.into_body();
+    let mut chunks = Vec::new();
+    while let Some(chunk) = body.data().await {
+        chunks.push(chunk.unwrap());
+    }
+    assert_eq!(chunks.len(), 2);
+
+    let lines: Vec<Value> = chunks.iter()
+        .flat_map(|chunk| std::str::from_utf8(chunk).unwrap().lines().map(str::to_string).collect::<Vec<_>>())
+        .map(|line| serde_json::from_str(&line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), EXPORT_PAGE_SIZE + 3);
+    assert_eq!(lines[0]["email"], "clerk0000@vandelay.com");
+    assert_eq!(lines[EXPORT_PAGE_SIZE]["email"], format!("clerk{:04}@vandelay.com", EXPORT_PAGE_SIZE));
+    assert!(lines.iter().all(|line| line.get("password").is_none()));
+}
+
+#[tokio::test]
+async fn test_csv_is_chosen_by_accept_or_format() {
+    let app = create_test_app(2).await;
+
+    let response = export(app.clone(), "/users/export", Some("text/csv")).await;
+    assert_eq!(response.headers()["content-type"], "text/csv");
+    assert_eq!(
+        body_text(response).await,
+        "id,email,fullname,role\n1,clerk0000@vandelay.com,\"Clerk, No. 0\",latex\n2,clerk0001@vandelay.com,\"Clerk, No. 1\",latex\n"
+    );
+
+    // The parameter wins over Accept
+    let response = export(app, "/users/export?format=csv", Some("application/x-ndjson")).await;
+    assert_eq!(response.headers()["content-type"], "text/csv");
+}
+
+#[tokio::test]
+async fn test_fields_are_selected_in_order() {
+    let app = create_test_app(1).await;
+
+    let response = export(app.clone(), "/users/export?format=csv&fields=role,email", None).await;
+    assert_eq!(body_text(response).await, "role,email\nlatex,clerk0000@vandelay.com\n");
+
+    let response = export(app, "/users/export?fields=fullname,id", None).await;
+    assert_eq!(body_text(response).await, "{\"fullname\":\"Clerk, No. 0\",\"id\":1}\n");
+}
+
+#[tokio::test]
+async fn test_rejected_exports() {
+    let app = create_test_app(1).await;
+
+    let response = export(app.clone(), "/users/export?fields=email,password", None).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = export(app.clone(), "/users/export?format=xlsx", None).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = export(app.clone(), "/users/export", Some("application/json")).await;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+    // Looking up a user is unaffected by the export route
+    let response = export(app, "/users/clerk0000@vandelay.com", None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,125 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+};
+use tower::ServiceExt;
+use serde_json::{json, Value};
+use hvalfangst_rust_crud_with_axum::users::{
+    model::UpsertUser,
+    router::users_routes,
+    service::UserStore
+};
+
+fn create_test_app() -> axum::Router {
+    let hashmap = UserStore::new();
+    users_routes(hashmap)
+}
+
+fn kramer() -> UpsertUser {
+    UpsertUser {
+        email: "kramer@kramerica.com".to_string(),
+        password: "giddyup".to_string(),
+        fullname: "Cosmo Kramer".to_string(),
+        role: "intern".to_string(),
+    }
+}
+
+fn to_cbor<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    ciborium::into_writer(value, &mut buffer).unwrap();
+    buffer
+}
+
+async fn post_user(app: axum::Router, content_type: &str, accept: Option<&str>, body: Vec<u8>) -> Response {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header("content-type", content_type);
+    if let Some(accept) = accept {
+        builder = builder.header("accept", accept);
+    }
+
+    app.oneshot(builder.body(Body::from(body)).unwrap()).await.unwrap()
+}
+
+async fn body_bytes(response: Response) -> Vec<u8> {
+    hyper::body::to_bytes(response.into_body()).await.unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn test_cbor_round_trip() {
+    let response = post_user(create_test_app(), "application/cbor", Some("application/cbor"), to_cbor(&kramer())).await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+
+    let user: Value = ciborium::from_reader(body_bytes(response).await.as_slice()).unwrap();
+    assert_eq!(user["email"], "kramer@kramerica.com");
+    assert_eq!(user["fullname"], "Cosmo Kramer");
+}
+
+#[tokio::test]
+async fn test_msgpack_round_trip() {
+    let app = create_test_app();
+    let body = rmp_serde::to_vec_named(&kramer()).unwrap();
+
+    let response = post_user(app.clone(), "application/msgpack", Some("application/x-msgpack"), body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["content-type"], "application/msgpack");
+
+    // The stored user is the same whichever format it was written in
+    let request = Request::builder()
+        .uri("/users/kramer@kramerica.com")
+        .header("accept", "application/msgpack")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let user: Value = rmp_serde::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(user["role"], "intern");
+}
+
+#[tokio::test]
+async fn test_json_is_the_fallback() {
+    for accept in [None, Some("*/*"), Some("text/html;q=0.9, application/*;q=0.5")] {
+        let body = serde_json::to_vec(&kramer()).unwrap();
+        let response = post_user(create_test_app(), "application/json", accept, body).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED, "{:?}", accept);
+        assert_eq!(response.headers()["content-type"], "application/json", "{:?}", accept);
+    }
+}
+
+#[tokio::test]
+async fn test_unsupported_accept_is_not_acceptable() {
+    let response = post_user(create_test_app(), "application/cbor", Some("text/html"), to_cbor(&kramer())).await;
+
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.headers()["content-type"], "application/json");
+
+    let error: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert!(error["error"].as_str().unwrap().contains("application/msgpack"));
+}
+
+#[tokio::test]
+async fn test_unsupported_content_type_is_rejected() {
+    let response = post_user(create_test_app(), "text/plain", None, b"Hello, Newman".to_vec()).await;
+
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn test_binary_body_rejections_name_the_field() {
+    let body = json!({"email": "kramer@kramerica.com", "password": "giddyup", "fullname": "Cosmo Kramer", "role": 7});
+
+    let response = post_user(create_test_app(), "application/cbor", None, to_cbor(&body)).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let error: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(error["field"], "role");
+
+    let response = post_user(create_test_app(), "application/msgpack", None, vec![0xc1]).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use hvalfangst_rust_crud_with_axum::{
+    health::{health_routes, DrainCheck, HealthRegistry},
+    shutdown::Drain,
+    storage::health::storage_checks,
+    users::service::UserStore,
+};
+
+fn create_test_app(drain: &Drain) -> axum::Router {
+    let hashmap = UserStore::new();
+
+    let mut registry = HealthRegistry::new();
+    registry.register(Arc::new(DrainCheck(drain.clone())));
+    for check in storage_checks(&hashmap) {
+        registry.register(check);
+    }
+    health_routes(registry)
+}
+
+async fn get_json(app: axum::Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let response = app
+        .oneshot(Request::builder().method("GET").uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn test_liveness() {
+    let (status, body) = get_json(create_test_app(&Drain::new()), "/healthz").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "alive");
+}
+
+#[tokio::test]
+async fn test_readiness_reports_each_check() {
+    let (status, body) = get_json(create_test_app(&Drain::new()), "/readyz").await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+
+    let names: Vec<&str> = body["checks"].as_array().unwrap().iter()
+        .map(|check| check["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["draining", "storage", "migrations"]);
+}
+
+#[tokio::test]
+async fn test_not_ready_while_draining() {
+    let drain = Drain::new();
+    let app = create_test_app(&drain);
+
+    drain.start();
+    let (status, body) = get_json(app.clone(), "/readyz").await;
+
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"][0]["status"], "fail");
+    assert_eq!(body["checks"][1]["status"], "ok");
+
+    // Liveness is unaffected by draining
+    let (status, _) = get_json(app, "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+}
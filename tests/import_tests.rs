@@ -0,0 +1,115 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use serde_json::Value;
+use hvalfangst_rust_crud_with_axum::users::{
+    model::UpsertUser,
+    router::users_routes,
+    service::{create_user, get_user_by_email, UserStore}
+};
+
+const SPREADSHEET: &str = "\
+E-mail Address,Name,Job Title,Password,Notes
+jpeterman@catalog.com,J. Peterman,Owner,urban_sombrero,Burma
+elaine@benes.com,Elaine Benes,Editor,the_urban_sombrero,
+not-an-email,Bob Sacamano,Unknown,secret,
+newman@usps.com,,Mailman,hello_jerry,
+jpeterman@catalog.com,J. Peterman,Catalog Owner,himalayan_walking_shoes,duplicate row
+";
+
+async fn create_test_store() -> UserStore {
+    let hashmap = UserStore::new();
+    let elaine = UpsertUser {
+        email: "elaine@benes.com".to_string(),
+        password: "get_out".to_string(),
+        fullname: "Elaine Benes".to_string(),
+        role: "Assistant".to_string(),
+    };
+    create_user(elaine, &hashmap).await.unwrap();
+    hashmap
+}
+
+async fn import(store: &UserStore, uri: &str, content_type: &str, body: &str) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", content_type)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+
+    let response = users_routes(store.clone()).oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn actions(report: &Value) -> Vec<(u64, String)> {
+    report["rows"].as_array().unwrap().iter()
+        .map(|row| (row["row"].as_u64().unwrap(), row["action"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_dry_run_reports_without_changes() {
+    let store = create_test_store().await;
+
+    let (status, report) = import(&store, "/users/import?dry_run=true", "text/csv", SPREADSHEET).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(actions(&report), [
+        (2, "created".to_string()),
+        (3, "updated".to_string()),
+        (4, "rejected".to_string()),
+        (5, "rejected".to_string()),
+        (6, "updated".to_string()),
+    ]);
+    assert_eq!(report["rows"][3]["reason"], "Missing value for fullname");
+    assert!(get_user_by_email("jpeterman@catalog.com", &store).await.unwrap().is_none());
+    assert_eq!(get_user_by_email("elaine@benes.com", &store).await.unwrap().unwrap().role, "Assistant");
+}
+
+#[tokio::test]
+async fn test_import_upserts_by_email() {
+    let store = create_test_store().await;
+
+    let (status, report) = import(&store, "/users/import", "text/csv; charset=utf-8", SPREADSHEET).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((report["created"].as_u64(), report["updated"].as_u64(), report["rejected"].as_u64()), (Some(1), Some(2), Some(2)));
+
+    let elaine = get_user_by_email("elaine@benes.com", &store).await.unwrap().unwrap();
+    assert_eq!((elaine.id, elaine.role.as_str()), (1, "Editor"));
+
+    // The last row for an email wins
+    let peterman = get_user_by_email("jpeterman@catalog.com", &store).await.unwrap().unwrap();
+    assert_eq!(peterman.role, "Catalog Owner");
+    assert_eq!(peterman.password, "himalayan_walking_shoes");
+}
+
+#[tokio::test]
+async fn test_unrecognized_columns_are_mapped() {
+    let store = create_test_store().await;
+    let csv = "Contact;Secret;Who;Kind\nkruger@kruger.com;sleepy;Mr. Kruger;Boss\n".replace(';', ",");
+
+    let (status, error) = import(&store, "/users/import", "text/csv", &csv).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error["error"].as_str().unwrap().contains("email"));
+
+    let uri = "/users/import?map=Contact%3Demail,Secret%3Dpassword,Who%3Dfullname,Kind%3Drole";
+    let (status, report) = import(&store, uri, "text/csv", &csv).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["created"], 1);
+    assert_eq!(get_user_by_email("kruger@kruger.com", &store).await.unwrap().unwrap().fullname, "Mr. Kruger");
+}
+
+#[tokio::test]
+async fn test_only_csv_is_imported() {
+    let store = create_test_store().await;
+
+    let (status, _) = import(&store, "/users/import", "application/json", "[]").await;
+
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
@@ -1,7 +1,3 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex}
-};
 use axum::{
     body::Body,
     http::{Request, StatusCode},
@@ -10,11 +6,11 @@ use tower::ServiceExt;
 use serde_json::json;
 use hvalfangst_rust_crud_with_axum::users::{
     router::users_routes,
-    model::User,
+    service::UserStore,
 };
 
 fn create_test_app() -> axum::Router {
-    let hashmap: Arc<Mutex<HashMap<String, User>>> = Arc::new(Mutex::new(HashMap::new()));
+    let hashmap = UserStore::new();
     users_routes(hashmap)
 }
 
@@ -94,7 +90,7 @@ async fn test_create_user_invalid_email() {
 
 #[tokio::test]
 async fn test_create_duplicate_user() {
-    let hashmap: Arc<Mutex<HashMap<String, User>>> = Arc::new(Mutex::new(HashMap::new()));
+    let hashmap = UserStore::new();
     let app = users_routes(hashmap);
 
     let request_body = json!({
@@ -143,7 +139,7 @@ async fn test_create_duplicate_user() {
 
 #[tokio::test]
 async fn test_get_user_success() {
-    let hashmap: Arc<Mutex<HashMap<String, User>>> = Arc::new(Mutex::new(HashMap::new()));
+    let hashmap = UserStore::new();
     let app = users_routes(hashmap);
 
     // First create a user
@@ -212,7 +208,7 @@ async fn test_get_user_not_found() {
 
 #[tokio::test]
 async fn test_update_user_success() {
-    let hashmap: Arc<Mutex<HashMap<String, User>>> = Arc::new(Mutex::new(HashMap::new()));
+    let hashmap = UserStore::new();
     let app = users_routes(hashmap);
 
     // First create a user
@@ -294,7 +290,7 @@ async fn test_update_user_not_found() {
 
 #[tokio::test]
 async fn test_delete_user_success() {
-    let hashmap: Arc<Mutex<HashMap<String, User>>> = Arc::new(Mutex::new(HashMap::new()));
+    let hashmap = UserStore::new();
     let app = users_routes(hashmap);
 
     // First create a user
@@ -372,7 +368,7 @@ async fn test_delete_user_not_found() {
 
 #[tokio::test]
 async fn test_full_crud_workflow() {
-    let hashmap: Arc<Mutex<HashMap<String, User>>> = Arc::new(Mutex::new(HashMap::new()));
+    let hashmap = UserStore::new();
     let app = users_routes(hashmap);
 
     // 1. Create a user
@@ -465,3 +461,61 @@ async fn test_full_crud_workflow() {
 
     assert_eq!(final_get_response.status(), StatusCode::NOT_FOUND);
 }
+
+#[tokio::test]
+async fn test_incoming_request_id_is_echoed_in_response_and_error_body() {
+    let app = create_test_app();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/users/sue_ellen@mischke.com")
+                .header("x-request-id", "the-bra-less-wonder")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["x-request-id"], "the-bra-less-wonder");
+
+    let body = get_response_body(response.into_body()).await;
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(error["request_id"], "the-bra-less-wonder");
+}
+
+#[tokio::test]
+async fn test_request_id_is_generated_when_missing() {
+    let app = create_test_app();
+
+    let request_body = json!({
+        "email": "lloyd-braun-at-nyc",
+        "password": "serenity_now",
+        "fullname": "Lloyd Braun",
+        "role": "advisor"
+    });
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/users")
+                .header("content-type", "application/json")
+                .body(Body::from(request_body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let generated = response.headers()["x-request-id"].to_str().unwrap().to_string();
+    assert!(!generated.is_empty());
+
+    let body = get_response_body(response.into_body()).await;
+    let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+    assert_eq!(error["request_id"], generated);
+}
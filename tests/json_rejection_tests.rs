@@ -0,0 +1,95 @@
+use axum::{
+    body::Body,
+    extract::DefaultBodyLimit,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use serde_json::{json, Value};
+use hvalfangst_rust_crud_with_axum::users::{router::users_routes, service::UserStore};
+
+const MAX_BODY_BYTES: usize = 256;
+
+fn create_test_app() -> axum::Router {
+    let hashmap = UserStore::new();
+    users_routes(hashmap).layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+}
+
+async fn post_user(content_type: Option<&str>, body: String) -> (StatusCode, Value) {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri("/users")
+        .header("x-request-id", "the-summer-of-george");
+    if let Some(content_type) = content_type {
+        builder = builder.header("content-type", content_type);
+    }
+
+    let response = create_test_app().oneshot(builder.body(Body::from(body)).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: Value = serde_json::from_slice(&bytes).expect("rejections are JSON");
+
+    assert_eq!(error["request_id"], "the-summer-of-george");
+    (status, error)
+}
+
+#[tokio::test]
+async fn test_missing_field_names_the_field() {
+    let body = json!({"email": "george@vandelay.com", "password": "bosco", "fullname": "George Costanza"});
+
+    let (status, error) = post_user(Some("application/json"), body.to_string()).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["field"], "role");
+    assert!(error["error"].as_str().unwrap().contains("missing field `role`"));
+}
+
+#[tokio::test]
+async fn test_type_mismatch_names_the_field() {
+    let body = json!({"email": "george@vandelay.com", "password": "bosco", "fullname": "George Costanza", "role": 42});
+
+    let (status, error) = post_user(Some("application/json"), body.to_string()).await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["field"], "role");
+    assert!(error["error"].as_str().unwrap().contains("invalid type"));
+}
+
+#[tokio::test]
+async fn test_malformed_json_is_bad_request() {
+    let complete = json!({"email": "george@vandelay.com", "password": "bosco", "fullname": "George Costanza", "role": "architect"});
+    let truncated = r#"{"email": "george@vandelay.com", "pass"#.to_string();
+
+    for body in [truncated, format!("{} trailing", complete)] {
+        let (status, error) = post_user(Some("application/json"), body).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(error["error"].as_str().unwrap().starts_with("Invalid request body"));
+    }
+}
+
+#[tokio::test]
+async fn test_wrong_content_type_is_unsupported() {
+    let body = json!({"email": "george@vandelay.com", "password": "bosco", "fullname": "George Costanza", "role": "architect"});
+
+    for content_type in [Some("text/plain"), None] {
+        let (status, error) = post_user(content_type, body.to_string()).await;
+
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(error["error"].as_str().unwrap().contains("application/json"));
+    }
+}
+
+#[tokio::test]
+async fn test_oversized_body_is_rejected() {
+    let body = json!({
+        "email": "george@vandelay.com",
+        "password": "bosco",
+        "fullname": "Art Vandelay ".repeat(MAX_BODY_BYTES),
+        "role": "importer_exporter"
+    });
+
+    let (status, error) = post_user(Some("application/json"), body.to_string()).await;
+
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(error["error"], "Request body is too large");
+}
@@ -0,0 +1,78 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use serde_json::json;
+use hvalfangst_rust_crud_with_axum::{
+    metrics::metrics_routes,
+    users::{router::users_routes, service::UserStore},
+};
+
+fn create_test_app() -> axum::Router {
+    let hashmap = UserStore::new();
+    users_routes(hashmap.clone()).merge(metrics_routes(hashmap))
+}
+
+async fn scrape(app: axum::Router) -> String {
+    let response = app
+        .oneshot(Request::builder().method("GET").uri("/metrics").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_exposes_traffic_store_and_process_metrics() {
+    let app = create_test_app();
+
+    let create_body = json!({
+        "email": "bania@comedy.com",
+        "password": "thats_gold_jerry",
+        "fullname": "Kenny Bania",
+        "role": "comedian"
+    });
+
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/users")
+                .header("content-type", "application/json")
+                .body(Body::from(create_body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/users/ovaltine@comedy.com")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let body = scrape(app).await;
+
+    assert!(body.contains(r#"http_requests_total{method="POST",path="/users",status="201"}"#));
+    assert!(body.contains(r#"http_requests_total{method="GET",path="/users/:email",status="404"}"#));
+    assert!(body.contains(r#"http_request_duration_seconds_bucket{method="GET",path="/users/:email",status="404""#));
+    assert!(body.contains(r#"store_operation_duration_seconds_count{operation="create_user"}"#));
+    assert!(body.contains(r#"store_operation_duration_seconds_count{operation="get_user_by_email"}"#));
+    assert!(body.contains("users_in_store 1"));
+
+    // Emails must never become label values
+    assert!(!body.contains("ovaltine@comedy.com"));
+
+    #[cfg(target_os = "linux")]
+    assert!(body.contains("process_resident_memory_bytes"));
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeSet;
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+};
+use tower::ServiceExt;
+use serde_json::{json, Value};
+use hvalfangst_rust_crud_with_axum::{
+    api::api_routes,
+    config::ApiConfig,
+    openapi::{docs_routes, openapi_routes},
+    users::{router::{users_route_table, users_routes}, service::UserStore},
+};
+
+const METHODS: [&str; 5] = ["get", "post", "put", "delete", "patch"];
+
+// Without the root alias, only paths under the documented server URL are reachable
+fn create_test_app() -> axum::Router {
+    let hashmap = UserStore::new();
+    let config = ApiConfig { root_alias_enabled: false, ..ApiConfig::default() };
+    api_routes(&config, [("v1", users_routes(hashmap))]).merge(openapi_routes())
+}
+
+async fn send(app: axum::Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let mut builder = Request::builder()
+        .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+        .uri(uri);
+    let body = match body {
+        Some(body) => {
+            builder = builder.header("content-type", "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+
+    let response = app.oneshot(builder.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn fetch_spec() -> Value {
+    let (status, spec) = send(create_test_app(), "get", "/openapi.json", None).await;
+    assert_eq!(status, StatusCode::OK);
+    spec
+}
+
+fn sample_user() -> Value {
+    json!({
+        "email": "kenny@rogers.com",
+        "password": "roasters",
+        "fullname": "Kenny Rogers",
+        "role": "chicken"
+    })
+}
+
+fn schema_properties(spec: &Value, schema: &str) -> BTreeSet<String> {
+    spec["components"]["schemas"][schema]["properties"].as_object()
+        .unwrap_or_else(|| panic!("schema {} is missing", schema))
+        .keys()
+        .cloned()
+        .collect()
+}
+
+fn object_keys(value: &Value) -> BTreeSet<String> {
+    value.as_object().unwrap().keys().cloned().collect()
+}
+
+// Every serialized field must be documented and every required property must be serialized
+fn assert_matches_schema(spec: &Value, schema: &str, value: &Value) {
+    let properties = schema_properties(spec, schema);
+    let required: BTreeSet<String> = spec["components"]["schemas"][schema]["required"].as_array()
+        .map(|required| required.iter().map(|name| name.as_str().unwrap().to_string()).collect())
+        .unwrap_or_default();
+    let keys = object_keys(value);
+
+    assert!(keys.is_subset(&properties), "{} is missing properties {:?}", schema, keys.difference(&properties));
+    assert!(required.is_subset(&keys), "{} requires properties {:?} that were not sent", schema, required.difference(&keys));
+}
+
+#[tokio::test]
+async fn test_spec_is_openapi_3_1() {
+    let spec = fetch_spec().await;
+
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+    assert!(spec["paths"].as_object().unwrap().contains_key("/users"));
+}
+
+#[tokio::test]
+async fn test_every_documented_operation_is_implemented() {
+    let spec = fetch_spec().await;
+
+    let server = spec["servers"][0]["url"].as_str().unwrap();
+
+    for (path, operations) in spec["paths"].as_object().unwrap() {
+        let uri = format!("{}{}", server, path.replace("{email}", "kenny@rogers.com"));
+
+        for method in METHODS {
+            let body = Some(sample_user());
+
+            match operations.get(method) {
+                // A documented operation must reach its handler and answer with a documented status
+                Some(operation) => {
+                    let (status, _) = send(create_test_app(), method, &uri, body).await;
+                    let documented: Vec<&String> = operation["responses"].as_object().unwrap().keys().collect();
+                    assert!(
+                        documented.contains(&&status.as_u16().to_string()),
+                        "{} {} answered {} which is not one of the documented statuses {:?}", method, path, status, documented
+                    );
+                }
+                // Any method implemented on a documented path must be documented as well
+                None => {
+                    let (status, _) = send(create_test_app(), method, &uri, body).await;
+                    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{} {} is implemented but not documented", method, path);
+                }
+            }
+        }
+    }
+}
+
+// The spec documents every route the router serves and nothing else
+#[tokio::test]
+async fn test_spec_and_router_list_the_same_operations() {
+    let spec = fetch_spec().await;
+
+    let documented: BTreeSet<(String, String)> = spec["paths"].as_object().unwrap().iter()
+        .flat_map(|(path, operations)| {
+            operations.as_object().unwrap().keys()
+                .filter(|method| METHODS.contains(&method.as_str()))
+                .map(move |method| (method.to_uppercase(), path.clone()))
+        })
+        .collect();
+    let routed: BTreeSet<(String, String)> = users_route_table().into_iter()
+        .map(|(method, path, _)| (method.to_string(), path.replace(":email", "{email}")))
+        .collect();
+
+    assert_eq!(
+        documented, routed,
+        "undocumented routes: {:?}, unrouted operations: {:?}",
+        routed.difference(&documented).collect::<Vec<_>>(),
+        documented.difference(&routed).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_schemas_match_serialized_models() {
+    let spec = fetch_spec().await;
+    let app = create_test_app();
+
+    assert_matches_schema(&spec, "UpsertUser", &sample_user());
+
+    let (status, created) = send(app.clone(), "post", "/v1/users", Some(sample_user())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_matches_schema(&spec, "User", &created);
+
+    let (status, error) = send(app.clone(), "get", "/v1/users/babs@rogers.com", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_matches_schema(&spec, "ErrorBody", &error);
+
+    let (status, rejected) = send(app.clone(), "post", "/v1/users", Some(json!({"email": "babs@rogers.com"}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_matches_schema(&spec, "ErrorBody", &rejected);
+    assert_eq!(rejected["field"], "password");
+
+    let (status, message) = send(app, "delete", "/v1/users/kenny@rogers.com", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_matches_schema(&spec, "MessageBody", &message);
+}
+
+#[tokio::test]
+async fn test_docs_are_served_from_the_binary() {
+    let app = docs_routes();
+
+    let response = app.clone()
+        .oneshot(Request::builder().uri("/docs").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()["location"], "/docs/");
+
+    let response = app.clone()
+        .oneshot(Request::builder().uri("/docs/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+
+    // The initializer is rewritten to point the UI at the spec served by this binary
+    let response = app.clone()
+        .oneshot(Request::builder().uri("/docs/swagger-initializer.js").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert!(String::from_utf8_lossy(&bytes).contains("/openapi.json"));
+
+    let response = app
+        .oneshot(Request::builder().uri("/docs/swagger-ui-bundle.js").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_unknown_docs_asset_is_not_found() {
+    let response = docs_routes()
+        .oneshot(Request::builder().uri("/docs/festivus.js").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
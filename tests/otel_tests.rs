@@ -0,0 +1,209 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex}
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use opentelemetry_proto::tonic::{
+    collector::trace::v1::{
+        trace_service_server::{TraceService, TraceServiceServer},
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    },
+    common::v1::any_value::Value,
+    trace::v1::Span,
+};
+use prost::Message;
+use tokio_stream::wrappers::TcpListenerStream;
+use tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+use hvalfangst_rust_crud_with_axum::{
+    config::{OtelConfig, OtlpProtocol},
+    telemetry::{otel_layer, tracer_provider},
+    users::{router::users_routes, service::UserStore},
+};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+// Everything the stub receivers were sent, shared with the test body
+type Received = Arc<Mutex<Vec<ExportTraceServiceRequest>>>;
+
+// - - - - - - - - - - - [STUB RECEIVERS] - - - - - - - - - - -
+
+struct GrpcReceiver(Received);
+
+#[tonic::async_trait]
+impl TraceService for GrpcReceiver {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportTraceServiceRequest>,
+    ) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+        self.0.lock().unwrap().push(request.into_inner());
+        Ok(tonic::Response::new(ExportTraceServiceResponse { partial_success: None }))
+    }
+}
+
+async fn start_grpc_receiver(received: Received) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(TraceServiceServer::new(GrpcReceiver(received)))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    addr
+}
+
+async fn http_receiver_handler(State(received): State<Received>, body: Bytes) -> StatusCode {
+    let request = ExportTraceServiceRequest::decode(body).unwrap();
+    received.lock().unwrap().push(request);
+    StatusCode::OK
+}
+
+async fn start_http_receiver(received: Received) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new()
+        .route("/v1/traces", post(http_receiver_handler))
+        .with_state(received);
+
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+    addr
+}
+
+// - - - - - - - - - - - [HELPERS] - - - - - - - - - - -
+
+// Sends one traced request through the users router and flushes the exporter
+async fn export_request_span(config: OtelConfig) {
+    let provider = tracer_provider(&config).unwrap();
+    let subscriber = tracing_subscriber::registry().with(otel_layer(&provider));
+
+    {
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let hashmap = UserStore::new();
+
+        let response = users_routes(hashmap)
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/users/puddy@saab.com")
+                    .header("traceparent", format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn exported_spans(received: &Received) -> Vec<Span> {
+    received.lock().unwrap().iter()
+        .flat_map(|request| request.resource_spans.iter())
+        .flat_map(|resource| resource.scope_spans.iter())
+        .flat_map(|scope| scope.spans.iter().cloned())
+        .collect()
+}
+
+fn exported_service_names(received: &Received) -> Vec<String> {
+    received.lock().unwrap().iter()
+        .flat_map(|request| request.resource_spans.iter())
+        .filter_map(|resource| resource.resource.as_ref())
+        .flat_map(|resource| resource.attributes.iter())
+        .filter(|attribute| attribute.key == "service.name")
+        .filter_map(|attribute| match attribute.value.as_ref().and_then(|value| value.value.as_ref()) {
+            Some(Value::StringValue(name)) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn assert_request_span_continues_incoming_trace(received: &Received) {
+    let spans = exported_spans(received);
+
+    let request_span = spans.iter().find(|span| span.name == "http_request").expect("http_request span exported");
+    assert_eq!(hex(&request_span.trace_id), TRACE_ID);
+    assert_eq!(hex(&request_span.parent_span_id), PARENT_SPAN_ID);
+
+    let service_span = spans.iter().find(|span| span.name == "get_user_by_email").expect("service span exported");
+    assert_eq!(hex(&service_span.trace_id), TRACE_ID);
+    assert_eq!(service_span.parent_span_id, request_span.span_id);
+
+    assert!(exported_service_names(received).iter().all(|name| name == "frogger"));
+}
+
+// - - - - - - - - - - - [TESTS] - - - - - - - - - - -
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_spans_are_exported_over_grpc() {
+    let received = Received::default();
+    let addr = start_grpc_receiver(received.clone()).await;
+
+    export_request_span(OtelConfig {
+        enabled: true,
+        endpoint: Some(format!("http://{}", addr)),
+        protocol: OtlpProtocol::Grpc,
+        service_name: "frogger".to_string(),
+        sampling_ratio: 1.0,
+    }).await;
+
+    assert_request_span_continues_incoming_trace(&received);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_spans_are_exported_over_http() {
+    let received = Received::default();
+    let addr = start_http_receiver(received.clone()).await;
+
+    export_request_span(OtelConfig {
+        enabled: true,
+        endpoint: Some(format!("http://{}/v1/traces", addr)),
+        protocol: OtlpProtocol::Http,
+        service_name: "frogger".to_string(),
+        sampling_ratio: 1.0,
+    }).await;
+
+    assert_request_span_continues_incoming_trace(&received);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_zero_sampling_ratio_without_parent_exports_nothing() {
+    let received = Received::default();
+    let addr = start_http_receiver(received.clone()).await;
+
+    let provider = tracer_provider(&OtelConfig {
+        enabled: true,
+        endpoint: Some(format!("http://{}/v1/traces", addr)),
+        protocol: OtlpProtocol::Http,
+        service_name: "frogger".to_string(),
+        sampling_ratio: 0.0,
+    }).unwrap();
+    let subscriber = tracing_subscriber::registry().with(otel_layer(&provider));
+
+    {
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let hashmap = UserStore::new();
+
+        users_routes(hashmap)
+            .oneshot(Request::builder().method("GET").uri("/users/puddy@saab.com").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+    }
+
+    tokio::task::spawn_blocking(move || provider.shutdown()).await.unwrap().unwrap();
+
+    assert!(exported_spans(&received).is_empty());
+}
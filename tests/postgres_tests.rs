@@ -0,0 +1,158 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use sqlx::{Connection, PgConnection};
+use tower::ServiceExt;
+use hvalfangst_rust_crud_with_axum::{
+    config::{Secret, StorageBackend, StorageConfig},
+    storage::{self, RepositoryError},
+    users::{
+        model::UpsertUser,
+        router::users_routes,
+        service::{create_user, create_users, get_user_by_email, get_users_page, update_user_by_email, BatchOutcome, UserStore}
+    },
+};
+
+// These tests need a server they may create databases on, e.g. TEST_DATABASE_URL=postgres://postgres@localhost:5432/postgres,
+// and are skipped without one. Each test gets a database of its own
+async fn test_config(name: &str) -> Option<StorageConfig> {
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL is not set, skipping");
+        return None;
+    };
+
+    let database = format!("users_test_{}", name);
+    let mut connection = PgConnection::connect(&url).await.expect("TEST_DATABASE_URL is reachable");
+    sqlx::query(&format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", database)).execute(&mut connection).await.unwrap();
+    sqlx::query(&format!("CREATE DATABASE {}", database)).execute(&mut connection).await.unwrap();
+
+    let (server, _) = url.split('?').next().unwrap().rsplit_once('/').unwrap();
+    let mut config = StorageConfig { backend: StorageBackend::Postgres, ..StorageConfig::default() };
+    config.postgres.url = Some(Secret::new(format!("{}/{}", server, database)));
+    Some(config)
+}
+
+async fn migrated_store(name: &str) -> Option<UserStore> {
+    let config = test_config(name).await?;
+    storage::migrate(&config).await.unwrap();
+    Some(storage::open(&config).await.unwrap())
+}
+
+fn request(email: &str, role: &str) -> UpsertUser {
+    UpsertUser {
+        email: email.to_string(),
+        password: "the_sea_was_angry".to_string(),
+        fullname: "George Costanza".to_string(),
+        role: role.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_migrations_are_applied_once() {
+    let Some(config) = test_config("migrations").await else { return };
+
+    let store = storage::open(&config).await.unwrap();
+    assert_eq!(store.repository().pending_migrations().await.unwrap(), ["0001_create_users", "0002_index_users_email_bytes"]);
+
+    // Asking left the database as it was
+    let mut connection = PgConnection::connect(config.postgres.url.as_ref().unwrap().expose()).await.unwrap();
+    let created: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL").fetch_one(&mut connection).await.unwrap();
+    assert!(!created);
+
+    assert_eq!(storage::migrate(&config).await.unwrap(), ["0001_create_users", "0002_index_users_email_bytes"]);
+    assert!(storage::migrate(&config).await.unwrap().is_empty());
+    assert!(store.repository().pending_migrations().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_normalized_email_is_unique() {
+    let Some(store) = migrated_store("unique_email").await else { return };
+
+    let created = create_user(request("George@Vandelay.com", "importer"), &store).await.unwrap();
+    assert_eq!(created.email, "George@Vandelay.com");
+
+    let duplicate = create_user(request(" george@vandelay.com", "exporter"), &store).await;
+    assert!(matches!(duplicate, Err(RepositoryError::Duplicate)));
+
+    let found = get_user_by_email("GEORGE@vandelay.com", &store).await.unwrap().unwrap();
+    assert_eq!((found.id, found.role.as_str()), (created.id, "importer"));
+}
+
+#[tokio::test]
+async fn test_update_keeps_id_and_email() {
+    let Some(store) = migrated_store("update").await else { return };
+    let created = create_user(request("george@yankees.com", "assistant_to_the_traveling_secretary"), &store).await.unwrap();
+
+    let updated = update_user_by_email("george@yankees.com", request("george@playnow.com", "hand_model"), &store).await.unwrap().unwrap();
+    assert_eq!((updated.id, updated.email.as_str(), updated.role.as_str()), (created.id, "george@yankees.com", "hand_model"));
+
+    let missing = update_user_by_email("george@kruger.com", request("george@kruger.com", "slacker"), &store).await.unwrap();
+    assert!(missing.is_none());
+}
+
+#[tokio::test]
+async fn test_concurrent_updates_each_apply() {
+    let Some(store) = migrated_store("concurrent_updates").await else { return };
+    create_user(request("george@costanza.com", "unemployed"), &store).await.unwrap();
+
+    let updates = ["architect", "marine_biologist", "latex_salesman", "hand_model"].map(|role| {
+        let store = store.clone();
+        tokio::spawn(async move { update_user_by_email("george@costanza.com", request("george@costanza.com", role), &store).await })
+    });
+    for update in updates {
+        assert!(update.await.unwrap().unwrap().is_some());
+    }
+
+    let user = get_user_by_email("george@costanza.com", &store).await.unwrap().unwrap();
+    assert!(["architect", "marine_biologist", "latex_salesman", "hand_model"].contains(&user.role.as_str()));
+}
+
+#[tokio::test]
+async fn test_all_or_nothing_batch_is_rolled_back() {
+    let Some(store) = migrated_store("batch").await else { return };
+    create_user(request("frank@costanza.com", "festivus_founder"), &store).await.unwrap();
+
+    let requests = vec![request("estelle@costanza.com", "mother"), request("FRANK@costanza.com", "father")];
+    let outcomes = create_users(requests, true, &store).await.unwrap();
+    assert!(matches!(outcomes[..], [BatchOutcome::Skipped, BatchOutcome::Duplicate]));
+    assert!(get_user_by_email("estelle@costanza.com", &store).await.unwrap().is_none());
+
+    let requests = vec![request("estelle@costanza.com", "mother"), request("Estelle@Costanza.com", "mother")];
+    let outcomes = create_users(requests, false, &store).await.unwrap();
+    assert!(matches!(outcomes[..], [BatchOutcome::Created(_), BatchOutcome::Duplicate]));
+}
+
+#[tokio::test]
+async fn test_pages_are_ordered_by_email() {
+    let Some(store) = migrated_store("pages").await else { return };
+    for email in ["uncle@leo.com", "Aunt@baby.com", "jerry@seinfeld.com", "elaine@benes.com"] {
+        create_user(request(email, "family"), &store).await.unwrap();
+    }
+
+    let page = get_users_page(None, 3, &store).await.unwrap();
+    let emails: Vec<&str> = page.users.iter().map(|user| user.email.as_str()).collect();
+    assert_eq!(emails, ["Aunt@baby.com", "elaine@benes.com", "jerry@seinfeld.com"]);
+
+    let page = get_users_page(page.next.as_ref(), 3, &store).await.unwrap();
+    assert_eq!(page.users.len(), 1);
+    assert_eq!(page.next, None);
+}
+
+#[tokio::test]
+async fn test_duplicate_is_reported_by_the_route() {
+    let Some(store) = migrated_store("routes").await else { return };
+    let body = r#"{"email": "susan@ross.com", "password": "envelopes", "fullname": "Susan Ross", "role": "nbc_executive"}"#;
+
+    for expected in [StatusCode::CREATED, StatusCode::ALREADY_REPORTED] {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/users")
+            .header("content-type", "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        let response = users_routes(store.clone()).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), expected);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration
+};
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+    response::Response,
+};
+use tower::ServiceExt;
+use serde_json::json;
+use hvalfangst_rust_crud_with_axum::{
+    api::api_routes,
+    config::{ApiConfig, LimitPolicy, RateLimitConfig, RouteLimit, Secret},
+    rate_limit::{memory::MemoryBackend, rate_limited, RateLimiter},
+    users::{router::{rewrite_batch_alias, users_routes}, service::UserStore},
+};
+
+const CLIENT: &str = "203.0.113.7:50000";
+const PROXY: &str = "10.0.0.1:443";
+
+fn create_test_app(config: RateLimitConfig) -> axum::Router {
+    let hashmap = UserStore::new();
+    let api = api_routes(&ApiConfig::default(), [("v1", users_routes(hashmap))]);
+    let limiter = RateLimiter::new(config, vec![Secret::new("vandelay"), Secret::new("kramerica")], Arc::new(MemoryBackend::new()));
+    rate_limited(api, Arc::new(limiter))
+}
+
+fn strict_config() -> RateLimitConfig {
+    RateLimitConfig {
+        enabled: true,
+        default: LimitPolicy { capacity: 5, refill_per_sec: 1.0 },
+        routes: vec![RouteLimit {
+            method: "POST".to_string(),
+            path: "/users".to_string(),
+            capacity: 2,
+            refill_per_sec: 0.5,
+        }],
+        trusted_proxies: vec![PROXY.parse::<SocketAddr>().unwrap().ip()],
+    }
+}
+
+fn request(method: &str, uri: &str, peer: &str) -> axum::http::request::Builder {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .extension(ConnectInfo(peer.parse::<SocketAddr>().unwrap()))
+}
+
+async fn create_user(app: axum::Router, builder: axum::http::request::Builder) -> Response {
+    let body = json!({
+        "email": "newman@usps.com",
+        "password": "hello_jerry",
+        "fullname": "Newman",
+        "role": "mailman"
+    });
+
+    app.oneshot(
+        builder
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn post_from(app: axum::Router, uri: &str, peer: &str) -> Response {
+    create_user(app, request("POST", uri, peer)).await
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_route_limit_rejects_with_headers() {
+    let app = create_test_app(strict_config());
+
+    let response = post_from(app.clone(), "/v1/users", CLIENT).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["ratelimit-limit"], "2");
+    assert_eq!(response.headers()["ratelimit-remaining"], "1");
+    assert_eq!(response.headers()["ratelimit-reset"], "2");
+
+    // The root alias draws from the same bucket as the versioned path
+    let response = post_from(app.clone(), "/users", CLIENT).await;
+    assert_eq!(response.status(), StatusCode::ALREADY_REPORTED);
+
+    let response = post_from(app.clone(), "/v1/users", CLIENT).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["ratelimit-remaining"], "0");
+    assert_eq!(response.headers()["retry-after"], "2");
+    assert!(response.headers().contains_key("x-request-id"));
+
+    let request_id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+    let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(error["error"], "Too many requests");
+    assert_eq!(error["request_id"], request_id);
+
+    tokio::time::advance(Duration::from_secs(2)).await;
+
+    let response = post_from(app, "/v1/users", CLIENT).await;
+    assert_eq!(response.status(), StatusCode::ALREADY_REPORTED);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_routes_without_limit_use_default_bucket() {
+    let app = create_test_app(strict_config());
+
+    for _ in 0..2 {
+        post_from(app.clone(), "/v1/users", CLIENT).await;
+    }
+
+    let response = app.oneshot(request("GET", "/v1/users/newman@usps.com", CLIENT).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["ratelimit-limit"], "5");
+    assert_eq!(response.headers()["ratelimit-remaining"], "4");
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_api_keys_have_their_own_buckets() {
+    let app = create_test_app(strict_config());
+
+    for _ in 0..2 {
+        post_from(app.clone(), "/v1/users", CLIENT).await;
+    }
+    assert_eq!(post_from(app.clone(), "/v1/users", CLIENT).await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // A configured key is limited on its own, wherever the request comes from
+    let response = create_user(app.clone(), request("POST", "/v1/users", CLIENT).header("x-api-key", "kramerica")).await;
+    assert_eq!(response.headers()["ratelimit-remaining"], "1");
+
+    // An unknown key does not escape the limit of the address it comes from
+    let response = create_user(app, request("POST", "/v1/users", CLIENT).header("x-api-key", "pendant")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_forwarded_for_is_honored_only_from_trusted_proxy() {
+    let app = create_test_app(strict_config());
+
+    // Two clients behind the same trusted proxy are limited separately
+    for client in ["198.51.100.1", "198.51.100.2"] {
+        for _ in 0..2 {
+            let builder = request("POST", "/v1/users", PROXY).header("x-forwarded-for", client);
+            assert_ne!(create_user(app.clone(), builder).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+    }
+
+    // A client connecting directly cannot pick its bucket by forging the header
+    for forged in ["198.51.100.3", "198.51.100.4"] {
+        let builder = request("POST", "/v1/users", CLIENT).header("x-forwarded-for", forged);
+        create_user(app.clone(), builder).await;
+    }
+
+    let builder = request("POST", "/v1/users", CLIENT).header("x-forwarded-for", "198.51.100.5");
+    assert_eq!(create_user(app, builder).await.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_batches_are_limited_by_default() {
+    let app = create_test_app(RateLimitConfig::default());
+    let post_batch = |email: &str| {
+        let body = json!([{"email": email, "password": "hello_jerry", "fullname": "Newman", "role": "mailman"}]);
+        let request = request("POST", "/v1/users:batch", CLIENT)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        // As the server does before routing
+        app.clone().oneshot(rewrite_batch_alias(request))
+    };
+
+    let response = post_batch("newman@usps.com").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["ratelimit-limit"], "2");
+    assert_eq!(response.headers()["ratelimit-remaining"], "1");
+
+    post_batch("newman@post.office.com").await.unwrap();
+    let response = post_batch("newman@jerry.com").await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Single creates keep a bucket of their own
+    let response = post_from(app, "/v1/users", CLIENT).await;
+    assert_eq!(response.headers()["ratelimit-remaining"], "9");
+}
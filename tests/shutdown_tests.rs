@@ -0,0 +1,100 @@
+use std::{
+    net::{SocketAddr, TcpListener},
+    sync::Arc,
+    time::Duration
+};
+use axum::{routing::get, Router};
+use hyper::{Client, StatusCode};
+use hvalfangst_rust_crud_with_axum::{
+    health::{health_routes, DrainCheck, HealthRegistry},
+    server::serve_until_drained,
+    shutdown::Drain,
+};
+
+fn create_slow_app(delay: Duration) -> Router {
+    Router::new().route("/slow", get(move || async move {
+        tokio::time::sleep(delay).await;
+        "done"
+    }))
+}
+
+fn bind_local() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+#[tokio::test]
+async fn test_in_flight_request_completes_during_drain() {
+    let (listener, addr) = bind_local();
+    let drain = Drain::new();
+
+    let server_drain = drain.clone();
+    let server = tokio::spawn(async move {
+        serve_until_drained(listener, create_slow_app(Duration::from_millis(300)), &server_drain, Duration::ZERO, Duration::from_secs(5)).await
+    });
+
+    let request = tokio::spawn(async move {
+        Client::new().get(format!("http://{}/slow", addr).parse().unwrap()).await
+    });
+
+    // Start draining while the request is still being handled
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drain.start();
+    assert!(drain.is_draining());
+
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"done");
+
+    tokio::time::timeout(Duration::from_secs(2), server).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_drain_timeout_bounds_shutdown() {
+    let (listener, addr) = bind_local();
+    let drain = Drain::new();
+
+    let server_drain = drain.clone();
+    let server = tokio::spawn(async move {
+        serve_until_drained(listener, create_slow_app(Duration::from_secs(30)), &server_drain, Duration::ZERO, Duration::from_millis(100)).await
+    });
+
+    let request = tokio::spawn(async move {
+        Client::new().get(format!("http://{}/slow", addr).parse().unwrap()).await
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    drain.start();
+
+    // The server gives up on the stuck request once the drain timeout elapses
+    tokio::time::timeout(Duration::from_secs(2), server).await.unwrap().unwrap().unwrap();
+    request.abort();
+}
+
+#[tokio::test]
+async fn test_readiness_fails_before_listener_closes() {
+    let (listener, addr) = bind_local();
+    let drain = Drain::new();
+    let mut registry = HealthRegistry::new();
+    registry.register(Arc::new(DrainCheck(drain.clone())));
+
+    let server_drain = drain.clone();
+    let server = tokio::spawn(async move {
+        serve_until_drained(listener, health_routes(registry), &server_drain, Duration::from_millis(300), Duration::from_secs(5)).await
+    });
+    let readyz = format!("http://{}/readyz", addr);
+
+    let response = Client::new().get(readyz.parse().unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    drain.start();
+
+    // New connections are still accepted during the pre-stop delay, and told the server is not ready
+    let response = Client::new().get(readyz.parse().unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    tokio::time::timeout(Duration::from_secs(2), server).await.unwrap().unwrap().unwrap();
+    assert!(Client::new().get(readyz.parse().unwrap()).await.is_err());
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+};
+use tower::ServiceExt;
+use serde_json::Value;
+use hvalfangst_rust_crud_with_axum::{
+    admin::{admin_routes, AdminState},
+    config::{Secret, StorageConfig},
+    storage,
+    users::{model::UpsertUser, service::{create_user, get_user_by_email, UserStore}},
+};
+
+const ADMIN_TOKEN: &str = "festivus_for_the_rest_of_us";
+
+fn storage_config(name: Option<&str>) -> StorageConfig {
+    let mut config = StorageConfig::default();
+    config.snapshot.path = name.map(|name| {
+        let directory = std::env::temp_dir().join(format!("snapshot-route-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.join(name)
+    });
+    config
+}
+
+async fn create_test_store() -> UserStore {
+    let hashmap = UserStore::new();
+    let request = UpsertUser {
+        email: "frank@costanza.com".to_string(),
+        password: "serenity_now".to_string(),
+        fullname: "Frank Costanza".to_string(),
+        role: "festivus_founder".to_string(),
+    };
+    create_user(request, &hashmap).await.unwrap();
+    hashmap
+}
+
+async fn trigger(config: &StorageConfig, store: &UserStore, authorization: Option<&str>) -> Response {
+    let app = admin_routes(Secret::new(ADMIN_TOKEN), AdminState { storage: config.clone(), store: store.clone() });
+
+    let mut builder = Request::builder().method("POST").uri("/admin/snapshot");
+    if let Some(authorization) = authorization {
+        builder = builder.header("authorization", authorization);
+    }
+    app.oneshot(builder.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn body_json(response: Response) -> Value {
+    serde_json::from_slice(&hyper::body::to_bytes(response.into_body()).await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn test_snapshot_requires_admin_token() {
+    let config = storage_config(Some("unauthorized.json"));
+    let store = create_test_store().await;
+
+    for authorization in [None, Some("Bearer airing_of_grievances")] {
+        let response = trigger(&config, &store, authorization).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        assert!(response.headers().contains_key("x-request-id"));
+    }
+    assert!(!config.snapshot.path.unwrap().exists());
+}
+
+#[tokio::test]
+async fn test_triggered_snapshot_is_loaded_on_open() {
+    let config = storage_config(Some("triggered.json"));
+    let store = create_test_store().await;
+
+    let response = trigger(&config, &store, Some(&format!("Bearer {}", ADMIN_TOKEN))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let info = body_json(response).await;
+    assert_eq!(info["version"], 1);
+    assert_eq!(info["users"], 1);
+    assert_eq!(PathBuf::from(info["path"].as_str().unwrap()), config.snapshot.path.clone().unwrap());
+
+    let reopened = storage::open(&config).await.unwrap();
+    assert_eq!(get_user_by_email("frank@costanza.com", &reopened).await.unwrap().unwrap().role, "festivus_founder");
+}
+
+#[tokio::test]
+async fn test_snapshot_without_path_is_a_conflict() {
+    let config = storage_config(None);
+
+    let response = trigger(&config, &create_test_store().await, Some(&format!("Bearer {}", ADMIN_TOKEN))).await;
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert!(body_json(response).await["error"].as_str().unwrap().contains("storage.snapshot.path"));
+}
@@ -0,0 +1,354 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex}
+};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use hvalfangst_rust_crud_with_axum::{
+    config::{Secret, StorageBackend, StorageConfig},
+    storage::{self, RepositoryError},
+    users::{
+        model::UpsertUser,
+        service::{count_users, create_user, create_users, delete_user_by_email, get_all_users, get_user_by_email, get_users_page, update_user_by_email, BatchOutcome, UserStore}
+    },
+};
+
+const ACCOUNT: &str = "devstoreaccount1";
+const KEY: &str = "c2VyZW5pdHlfbm93";
+// Published by Azurite for devstoreaccount1
+const AZURITE_KEY: &str = "Eby8vdM02xNOcqFlqUwJPLlmEtlCDXJ1OUzFT50uSRZ6IFsuFq2UVErCz4I6tq/K1SZFPTOtr/KBHBeksoGMGw==";
+
+// Rows a query answers with at most, so that listing more takes continuation tokens
+const PAGE_SIZE: usize = 2;
+
+// - - - - - - - - - - - [STUB TABLE SERVICE] - - - - - - - - - - -
+
+// Just enough of the Table service, as Azurite serves it, for a single table
+#[derive(Default)]
+struct TableService {
+    tables: HashSet<String>,
+    entities: BTreeMap<(String, String), (Value, u64)>,
+    etags: u64,
+    // The next this many requests are answered 503 Service Unavailable
+    busy: usize,
+    // Queries of the users table answered so far
+    queries: usize,
+}
+
+type Shared = Arc<Mutex<TableService>>;
+
+fn etag(version: u64) -> String {
+    format!("W/\"datetime'{}'\"", version)
+}
+
+fn with_etag(status: StatusCode, version: u64) -> Response {
+    (status, [("ETag", etag(version))]).into_response()
+}
+
+fn unquote(value: &str) -> String {
+    value.replace("''", "'")
+}
+
+async fn table_service(
+    State(service): State<Shared>,
+    method: Method,
+    uri: Uri,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut service = service.lock().unwrap();
+    if service.busy > 0 {
+        service.busy -= 1;
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let date = headers["x-ms-date"].to_str().unwrap();
+    let mut mac = Hmac::<Sha256>::new_from_slice(&STANDARD.decode(KEY).unwrap()).unwrap();
+    mac.update(format!("{}\n/{}{}", date, ACCOUNT, uri.path()).as_bytes());
+    let expected = format!("SharedKeyLite {}:{}", ACCOUNT, STANDARD.encode(mac.finalize().into_bytes()));
+    if headers["authorization"] != expected.as_str() {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let path = percent_encoding::percent_decode_str(uri.path()).decode_utf8().unwrap();
+    let path = path.strip_prefix(&format!("/{}/", ACCOUNT)).unwrap();
+    let entity_path = Regex::new(r"^users\(PartitionKey='(.*)',RowKey='(.*)'\)$").unwrap();
+
+    if path == "Tables" {
+        let name = serde_json::from_slice::<Value>(&body).unwrap()["TableName"].as_str().unwrap().to_string();
+        return if service.tables.insert(name) { StatusCode::CREATED } else { StatusCode::CONFLICT }.into_response();
+    }
+    if let Some(name) = path.strip_prefix("Tables('").and_then(|name| name.strip_suffix("')")) {
+        return if service.tables.contains(name) { StatusCode::OK } else { StatusCode::NOT_FOUND }.into_response();
+    }
+    if !service.tables.contains("users") {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    if path == "users" && method == Method::POST {
+        let entity: Value = serde_json::from_slice(&body).unwrap();
+        let key = (entity["PartitionKey"].as_str().unwrap().to_string(), entity["RowKey"].as_str().unwrap().to_string());
+        if service.entities.contains_key(&key) {
+            return StatusCode::CONFLICT.into_response();
+        }
+        service.etags += 1;
+        let version = service.etags;
+        service.entities.insert(key, (entity, version));
+        return with_etag(StatusCode::NO_CONTENT, version);
+    }
+
+    if path == "users()" {
+        let filter = &query["$filter"];
+        let excluded = Regex::new(r"PartitionKey ne '((?:[^']|'')*)'").unwrap().captures(filter).map(|captures| unquote(&captures[1]));
+        let after = Regex::new(r"RowKey gt '((?:[^']|'')*)'").unwrap().captures(filter).map(|captures| unquote(&captures[1]));
+        let start = match (query.get("NextPartitionKey"), query.get("NextRowKey")) {
+            (Some(partition_key), Some(row_key)) => (partition_key.clone(), row_key.clone()),
+            _ => (String::new(), String::new()),
+        };
+
+        let top = query.get("$top").map_or(PAGE_SIZE, |top| top.parse::<usize>().unwrap().min(PAGE_SIZE));
+        service.queries += 1;
+
+        let mut rows = service.entities.range(start..)
+            .filter(|((partition_key, row_key), _)| {
+                excluded.as_ref() != Some(partition_key) && after.as_ref().is_none_or(|after| row_key > after)
+            });
+        let page: Vec<Value> = rows.by_ref().take(top).map(|(_, (entity, _))| entity.clone()).collect();
+        let mut response = Json(json!({ "value": page })).into_response();
+        if let Some(((partition_key, row_key), _)) = rows.next() {
+            response.headers_mut().insert("x-ms-continuation-NextPartitionKey", partition_key.parse().unwrap());
+            response.headers_mut().insert("x-ms-continuation-NextRowKey", row_key.parse().unwrap());
+        }
+        return response;
+    }
+
+    let captures = entity_path.captures(path).unwrap();
+    let key = (unquote(&captures[1]), unquote(&captures[2]));
+    let Some((entity, version)) = service.entities.get(&key).cloned() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if method == Method::GET {
+        let mut response = Json(entity).into_response();
+        response.headers_mut().insert("ETag", etag(version).parse().unwrap());
+        return response;
+    }
+    if headers["if-match"] != etag(version).as_str() {
+        return StatusCode::PRECONDITION_FAILED.into_response();
+    }
+    match method {
+        Method::PUT => {
+            service.etags += 1;
+            let version = service.etags;
+            service.entities.insert(key, (serde_json::from_slice(&body).unwrap(), version));
+            with_etag(StatusCode::NO_CONTENT, version)
+        }
+        Method::DELETE => {
+            service.entities.remove(&key);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+async fn start_table_service() -> (SocketAddr, Shared) {
+    let service = Shared::default();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().fallback(table_service).with_state(service.clone());
+
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+    (addr, service)
+}
+
+// - - - - - - - - - - - [HELPERS] - - - - - - - - - - -
+
+fn storage_config(addr: SocketAddr) -> StorageConfig {
+    let mut config = StorageConfig { backend: StorageBackend::Table, ..StorageConfig::default() };
+    config.table.endpoint = Some(format!("http://{}/{}", addr, ACCOUNT));
+    config.table.account = Some(ACCOUNT.to_string());
+    config.table.key = Some(Secret::new(KEY));
+    config.table.retry_delay_ms = 1;
+    config
+}
+
+async fn migrated_store() -> (UserStore, Shared) {
+    let (addr, service) = start_table_service().await;
+    let config = storage_config(addr);
+    storage::migrate(&config).await.unwrap();
+    (storage::open(&config).await.unwrap(), service)
+}
+
+fn request(email: &str, role: &str) -> UpsertUser {
+    UpsertUser {
+        email: email.to_string(),
+        password: "hennigans".to_string(),
+        fullname: "Cosmo Kramer".to_string(),
+        role: role.to_string(),
+    }
+}
+
+// - - - - - - - - - - - [TESTS] - - - - - - - - - - -
+
+#[tokio::test]
+async fn test_migration_creates_the_table() {
+    let (addr, _service) = start_table_service().await;
+    let config = storage_config(addr);
+
+    let store = storage::open(&config).await.unwrap();
+    assert_eq!(store.repository().pending_migrations().await.unwrap(), ["create_table_users"]);
+
+    assert_eq!(storage::migrate(&config).await.unwrap(), ["create_table_users"]);
+    assert!(storage::migrate(&config).await.unwrap().is_empty());
+    assert!(store.repository().pending_migrations().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_users_are_partitioned_by_domain() {
+    let (store, service) = migrated_store().await;
+
+    let created = create_user(request("kramer@kramerica.com", "intern_supervisor"), &store).await.unwrap();
+    create_user(request("Bob@Kramerica.COM", "intern"), &store).await.unwrap();
+    let duplicate = create_user(request("kramer@kramerica.com", "founder"), &store).await;
+    assert!(matches!(duplicate, Err(RepositoryError::Duplicate)));
+
+    let keys: Vec<(String, String)> = service.lock().unwrap().entities.keys().cloned().collect();
+    assert!(keys.contains(&("kramerica.com".to_string(), "kramer@kramerica.com".to_string())));
+    assert!(keys.contains(&("kramerica.com".to_string(), "Bob@Kramerica.COM".to_string())));
+
+    let found = get_user_by_email("kramer@kramerica.com", &store).await.unwrap().unwrap();
+    assert_eq!((found.id, found.role.as_str()), (created.id, "intern_supervisor"));
+    assert!(get_user_by_email("kramer@kramerica.org", &store).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_busy_service_is_retried() {
+    let (store, service) = migrated_store().await;
+
+    service.lock().unwrap().busy = 2;
+    create_user(request("newman@usps.gov", "mailman"), &store).await.unwrap();
+
+    service.lock().unwrap().busy = 100;
+    let result = get_user_by_email("newman@usps.gov", &store).await;
+    assert!(matches!(result, Err(RepositoryError::Backend(_))));
+}
+
+#[tokio::test]
+async fn test_concurrent_writers_do_not_lose_changes() {
+    let (store, _service) = migrated_store().await;
+
+    let creates: Vec<_> = (0..8).map(|n| {
+        let store = store.clone();
+        tokio::spawn(async move { create_user(request(&format!("kramer{}@kramerica.com", n), "intern"), &store).await })
+    }).collect();
+    let mut ids = HashSet::new();
+    for create in creates {
+        ids.insert(create.await.unwrap().unwrap().id);
+    }
+    assert_eq!(ids.len(), 8);
+
+    let updates = ["bagel_slicer", "latex_salesman", "hand_model", "stand_in"].map(|role| {
+        let store = store.clone();
+        tokio::spawn(async move { update_user_by_email("kramer0@kramerica.com", request("kramer0@kramerica.com", role), &store).await })
+    });
+    for update in updates {
+        assert!(update.await.unwrap().unwrap().is_some());
+    }
+    let user = get_user_by_email("kramer0@kramerica.com", &store).await.unwrap().unwrap();
+    assert!(["bagel_slicer", "latex_salesman", "hand_model", "stand_in"].contains(&user.role.as_str()));
+}
+
+#[tokio::test]
+async fn test_pages_follow_continuation_tokens() {
+    let (store, service) = migrated_store().await;
+    for email in ["lomez@gmail.com", "jerry@seinfeld.com", "bania@comedy.com", "elaine@benes.com", "kramer@kramerica.com"] {
+        create_user(request(email, "friend"), &store).await.unwrap();
+    }
+
+    // The stub answers with at most two rows, so every page ends early and continues where the service left off
+    let mut cursor = None;
+    let mut pages = Vec::new();
+    loop {
+        let page = get_users_page(cursor.as_ref(), 3, &store).await.unwrap();
+        pages.push(page.users.iter().map(|user| user.email.clone()).collect::<Vec<String>>());
+        cursor = page.next;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(pages, [
+        vec!["elaine@benes.com", "bania@comedy.com"],
+        vec!["lomez@gmail.com", "kramer@kramerica.com"],
+        vec!["jerry@seinfeld.com"],
+    ]);
+    assert_eq!(service.lock().unwrap().queries, 3);
+
+    // Counting would read the whole table
+    assert_eq!(count_users(&store).await.unwrap(), None);
+    let ids: Vec<i32> = get_all_users(&store).await.unwrap().iter().map(|user| user.id).collect();
+    assert_eq!(ids, [1, 2, 3, 4, 5]);
+}
+
+#[tokio::test]
+async fn test_all_or_nothing_batch_creates_nothing_on_duplicate() {
+    let (store, _service) = migrated_store().await;
+    create_user(request("frank@costanza.com", "festivus_founder"), &store).await.unwrap();
+
+    let requests = vec![request("estelle@costanza.com", "mother"), request("frank@costanza.com", "father")];
+    let outcomes = create_users(requests, true, &store).await.unwrap();
+    assert!(matches!(outcomes[..], [BatchOutcome::Skipped, BatchOutcome::Duplicate]));
+    assert!(get_user_by_email("estelle@costanza.com", &store).await.unwrap().is_none());
+
+    let requests = vec![request("estelle@costanza.com", "mother"), request("estelle@costanza.com", "mother")];
+    let outcomes = create_users(requests, false, &store).await.unwrap();
+    assert!(matches!(outcomes[..], [BatchOutcome::Created(_), BatchOutcome::Duplicate]));
+}
+
+// Runs against Azurite, e.g. TEST_AZURITE_URL=http://127.0.0.1:10002/devstoreaccount1, and is skipped without it.
+// Unlike the stub, Azurite checks signatures, continuation tokens and ETags the way the Table service does
+#[tokio::test]
+async fn test_azurite_round_trip() {
+    let Ok(url) = std::env::var("TEST_AZURITE_URL") else {
+        eprintln!("TEST_AZURITE_URL is not set, skipping");
+        return;
+    };
+    let mut config = StorageConfig { backend: StorageBackend::Table, ..StorageConfig::default() };
+    config.table.endpoint = Some(url);
+    config.table.account = Some(ACCOUNT.to_string());
+    config.table.key = Some(Secret::new(AZURITE_KEY));
+    config.table.table = format!("userstest{}", std::process::id());
+
+    assert_eq!(storage::migrate(&config).await.unwrap().len(), 1);
+    let store = storage::open(&config).await.unwrap();
+    assert!(store.repository().pending_migrations().await.unwrap().is_empty());
+
+    create_user(request("bania@comedy.com", "comedian"), &store).await.unwrap();
+    assert!(matches!(create_user(request("bania@comedy.com", "comedian"), &store).await, Err(RepositoryError::Duplicate)));
+    create_users(vec![request("jerry@seinfeld.com", "comedian"), request("lomez@gmail.com", "lawyer")], true, &store).await.unwrap();
+    update_user_by_email("bania@comedy.com", request("bania@comedy.com", "soup_fan"), &store).await.unwrap();
+    assert_eq!(get_user_by_email("bania@comedy.com", &store).await.unwrap().unwrap().role, "soup_fan");
+    assert!(delete_user_by_email("lomez@gmail.com", &store).await.unwrap().is_some());
+
+    let mut cursor = None;
+    let mut emails = Vec::new();
+    loop {
+        let page = get_users_page(cursor.as_ref(), 1, &store).await.unwrap();
+        emails.extend(page.users.into_iter().map(|user| user.email));
+        cursor = page.next;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(emails, ["bania@comedy.com", "jerry@seinfeld.com"]);
+}
@@ -0,0 +1,110 @@
+use std::{
+    io,
+    sync::{Arc, Mutex}
+};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt};
+use serde_json::json;
+use hvalfangst_rust_crud_with_axum::{
+    config::{LogConfig, LogFormat},
+    telemetry::fmt_layer,
+    users::{router::users_routes, service::UserStore},
+};
+
+// Collects everything the fmt layer writes so tests can inspect the emitted log lines
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    fn lines(&self) -> Vec<serde_json::Value> {
+        let buffer = self.0.lock().unwrap();
+        String::from_utf8_lossy(&buffer)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+impl io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLogs {
+    type Writer = CapturedLogs;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn test_requests_are_traced_with_route_template() {
+    let logs = CapturedLogs::default();
+    let config = LogConfig { format: LogFormat::Json, ..LogConfig::default() };
+    let subscriber = tracing_subscriber::registry().with(fmt_layer(&config, logs.clone()));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let hashmap = UserStore::new();
+    let app = users_routes(hashmap);
+
+    let create_body = json!({
+        "email": "jerry@seinfeld.com",
+        "password": "whats_the_deal",
+        "fullname": "Jerry Seinfeld",
+        "role": "comedian"
+    });
+
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/users")
+                .header("content-type", "application/json")
+                .body(Body::from(create_body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/users/jerry@seinfeld.com")
+                .header("x-request-id", "festivus-for-the-rest-of-us")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let lines = logs.lines();
+    let completed: Vec<&serde_json::Value> = lines.iter()
+        .filter(|line| line["fields"]["message"] == "request completed")
+        .collect();
+
+    assert_eq!(completed.len(), 2);
+    assert_eq!(completed[1]["span"]["method"], "GET");
+    assert_eq!(completed[1]["span"]["path"], "/users/:email");
+    assert_eq!(completed[1]["span"]["request_id"], "festivus-for-the-rest-of-us");
+    assert!(!completed[0]["span"]["request_id"].as_str().unwrap().is_empty());
+    assert_eq!(completed[1]["fields"]["status"], 200);
+    assert!(completed[1]["fields"]["latency_ms"].is_number());
+
+    // Service spans record the email in redacted form only
+    assert!(lines.iter().any(|line| line["span"]["email"] == "j***@seinfeld.com"));
+    assert!(lines.iter().all(|line| !line.to_string().contains("jerry@seinfeld.com")));
+}
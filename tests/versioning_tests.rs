@@ -0,0 +1,114 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::Response,
+    routing,
+    Router,
+};
+use tower::ServiceExt;
+use serde_json::json;
+use hvalfangst_rust_crud_with_axum::{
+    api::api_routes,
+    config::{ApiConfig, Config, VersionPolicy},
+    users::{router::users_routes, service::UserStore},
+};
+
+fn create_test_app(config: &ApiConfig) -> Router {
+    let hashmap = UserStore::new();
+    api_routes(config, [("v1", users_routes(hashmap))])
+}
+
+async fn fetch(app: Router, uri: &str) -> Response {
+    app.oneshot(Request::builder().method("GET").uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_root_paths_alias_v1() {
+    let app = create_test_app(&ApiConfig::default());
+
+    let create_body = json!({
+        "email": "lloyd@braun.com",
+        "password": "serenity_now",
+        "fullname": "Lloyd Braun",
+        "role": "advisor"
+    });
+
+    let response = app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/v1/users")
+                .header("content-type", "application/json")
+                .body(Body::from(create_body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Both paths are served by the same store
+    assert_eq!(fetch(app.clone(), "/v1/users/lloyd@braun.com").await.status(), StatusCode::OK);
+    assert_eq!(fetch(app, "/users/lloyd@braun.com").await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_root_alias_can_be_disabled() {
+    let config = Config {
+        api: ApiConfig { root_alias_enabled: false, ..ApiConfig::default() },
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+
+    let app = create_test_app(&config.api);
+
+    // Unknown routes answer with an empty body, unknown users with an error body
+    let response = fetch(app.clone(), "/users/lloyd@braun.com").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(hyper::body::to_bytes(response.into_body()).await.unwrap().is_empty());
+
+    let response = fetch(app, "/v1/users/lloyd@braun.com").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!hyper::body::to_bytes(response.into_body()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_deprecated_version_sends_lifecycle_headers() {
+    let mut config = ApiConfig::default();
+    config.versions.insert("v1".to_string(), VersionPolicy {
+        deprecated_at: Some("Sun, 01 Jun 2025 00:00:00 GMT".to_string()),
+        sunset_at: Some("Mon, 01 Dec 2025 00:00:00 GMT".to_string()),
+    });
+    let app = create_test_app(&config);
+
+    // Error responses carry the headers too, so a client sees them whatever the outcome of the call
+    for uri in ["/v1/users/lloyd@braun.com", "/users/lloyd@braun.com"] {
+        let response = fetch(app.clone(), uri).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["deprecation"], "@1748736000");
+        assert_eq!(response.headers()["sunset"], "Mon, 01 Dec 2025 00:00:00 GMT");
+    }
+}
+
+#[tokio::test]
+async fn test_versions_are_mounted_side_by_side() {
+    let hashmap = UserStore::new();
+    let mut config = ApiConfig::default();
+    config.versions.insert("v1".to_string(), VersionPolicy {
+        deprecated_at: Some("Sun, 01 Jun 2025 00:00:00 GMT".to_string()),
+        sunset_at: None,
+    });
+
+    let v2 = Router::new().route("/users/:email", routing::get(|| async { "v2" }));
+    let app = api_routes(&config, [("v1", users_routes(hashmap)), ("v2", v2)]);
+
+    let response = fetch(app.clone(), "/v2/users/lloyd@braun.com").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("deprecation"));
+
+    let response = fetch(app, "/v1/users/lloyd@braun.com").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(response.headers().contains_key("deprecation"));
+}
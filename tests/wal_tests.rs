@@ -0,0 +1,180 @@
+use std::{collections::HashMap, fs, sync::Arc};
+use async_trait::async_trait;
+use hvalfangst_rust_crud_with_axum::{
+    config::{FsyncPolicy, StorageConfig},
+    storage::{
+        self,
+        memory::{Change, Journal, MemoryRepository},
+        snapshot::write_snapshot,
+        wal::rotated_path,
+        RepositoryError
+    },
+    users::{
+        model::UpsertUser,
+        service::{create_user, create_users, delete_user_by_email, get_user_by_email, update_user_by_email, UserStore}
+    },
+};
+
+// A journal on a disk that has filled up
+struct FullDisk;
+
+#[async_trait]
+impl Journal for FullDisk {
+    async fn record(&self, _changes: &[Change]) -> Result<(), String> {
+        Err("No space left on device".to_string())
+    }
+
+    async fn sync(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn rotate(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn remove_rotated(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), String> {
+        Err("No space left on device".to_string())
+    }
+}
+
+fn storage_config(name: &str) -> StorageConfig {
+    let directory = std::env::temp_dir().join(format!("wal-route-tests-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+
+    let mut config = StorageConfig::default();
+    config.snapshot.path = Some(directory.join("users.json"));
+    config.wal.path = Some(directory.join("users.wal"));
+    config.wal.fsync = FsyncPolicy::Always;
+    config
+}
+
+fn request(email: &str, role: &str) -> UpsertUser {
+    UpsertUser {
+        email: email.to_string(),
+        password: "bubble_boy".to_string(),
+        fullname: "Resident of the Del Boca Vista".to_string(),
+        role: role.to_string(),
+    }
+}
+
+async fn exists(store: &UserStore, email: &str) -> bool {
+    get_user_by_email(email, store).await.unwrap().is_some()
+}
+
+fn log_len(config: &StorageConfig) -> u64 {
+    fs::metadata(config.wal.path.as_ref().unwrap()).unwrap().len()
+}
+
+// Cuts bytes off the end of the log, as a crash midway through writing its last record would
+fn truncate_log(config: &StorageConfig, bytes: u64) {
+    let file = fs::OpenOptions::new().write(true).open(config.wal.path.as_ref().unwrap()).unwrap();
+    file.set_len(log_len(config) - bytes).unwrap();
+}
+
+#[tokio::test]
+async fn test_changes_without_snapshot_survive_reopening() {
+    let config = storage_config("replay");
+    let store = storage::open(&config).await.unwrap();
+    create_user(request("morty@seinfeld.com", "retiree"), &store).await.unwrap();
+    create_user(request("helen@seinfeld.com", "retiree"), &store).await.unwrap();
+    update_user_by_email("morty@seinfeld.com", request("morty@seinfeld.com", "condo_president"), &store).await.unwrap();
+    delete_user_by_email("helen@seinfeld.com", &store).await.unwrap();
+    drop(store);
+
+    let reopened = storage::open(&config).await.unwrap();
+
+    assert_eq!(get_user_by_email("morty@seinfeld.com", &reopened).await.unwrap().unwrap().role, "condo_president");
+    assert!(!exists(&reopened, "helen@seinfeld.com").await);
+}
+
+#[tokio::test]
+async fn test_record_torn_midway_is_discarded() {
+    let config = storage_config("torn-payload");
+    let store = storage::open(&config).await.unwrap();
+    create_user(request("jack@klompus.com", "retiree"), &store).await.unwrap();
+    let intact = log_len(&config);
+    create_user(request("uncle@leo.com", "retiree"), &store).await.unwrap();
+    drop(store);
+    truncate_log(&config, 10);
+
+    let reopened = storage::open(&config).await.unwrap();
+    assert!(exists(&reopened, "jack@klompus.com").await);
+    assert!(!exists(&reopened, "uncle@leo.com").await);
+    assert_eq!(log_len(&config), intact);
+
+    // Appends after the discarded record are replayed on the next start
+    create_user(request("nana@seinfeld.com", "grandmother"), &reopened).await.unwrap();
+    drop(reopened);
+    let reopened = storage::open(&config).await.unwrap();
+    assert!(exists(&reopened, "nana@seinfeld.com").await);
+}
+
+#[tokio::test]
+async fn test_record_torn_in_its_header_is_discarded() {
+    let config = storage_config("torn-header");
+    let store = storage::open(&config).await.unwrap();
+    create_user(request("jack@klompus.com", "retiree"), &store).await.unwrap();
+    let intact = log_len(&config);
+    create_user(request("uncle@leo.com", "retiree"), &store).await.unwrap();
+    drop(store);
+    truncate_log(&config, log_len(&config) - intact - 3);
+
+    let reopened = storage::open(&config).await.unwrap();
+
+    assert!(exists(&reopened, "jack@klompus.com").await);
+    assert!(!exists(&reopened, "uncle@leo.com").await);
+    assert_eq!(log_len(&config), intact);
+}
+
+#[tokio::test]
+async fn test_snapshot_compacts_the_log() {
+    let config = storage_config("compaction");
+    let store = storage::open(&config).await.unwrap();
+    create_user(request("morty@seinfeld.com", "retiree"), &store).await.unwrap();
+    create_user(request("jack@klompus.com", "retiree"), &store).await.unwrap();
+
+    write_snapshot(config.snapshot.path.as_ref().unwrap(), &store).await.unwrap();
+    assert_eq!(log_len(&config), 0);
+    assert!(!rotated_path(config.wal.path.as_ref().unwrap()).exists());
+
+    delete_user_by_email("jack@klompus.com", &store).await.unwrap();
+    drop(store);
+
+    let reopened = storage::open(&config).await.unwrap();
+    assert!(exists(&reopened, "morty@seinfeld.com").await);
+    assert!(!exists(&reopened, "jack@klompus.com").await);
+}
+
+#[tokio::test]
+async fn test_failed_snapshot_keeps_the_rotated_log() {
+    let config = storage_config("failed-snapshot");
+    let store = storage::open(&config).await.unwrap();
+    create_user(request("morty@seinfeld.com", "retiree"), &store).await.unwrap();
+
+    let unwritable = config.snapshot.path.as_ref().unwrap().with_file_name("missing").join("users.json");
+    assert!(write_snapshot(&unwritable, &store).await.is_err());
+    create_user(request("jack@klompus.com", "retiree"), &store).await.unwrap();
+    drop(store);
+
+    let reopened = storage::open(&config).await.unwrap();
+    assert!(exists(&reopened, "morty@seinfeld.com").await);
+    assert!(exists(&reopened, "jack@klompus.com").await);
+}
+
+#[tokio::test]
+async fn test_changes_the_journal_refuses_are_not_applied() {
+    let store = UserStore::from_memory(MemoryRepository::new(HashMap::new()).with_journal(Arc::new(FullDisk)));
+
+    let created = create_user(request("morty@seinfeld.com", "retiree"), &store).await;
+    assert!(matches!(created, Err(RepositoryError::Backend(_))));
+    let batch = create_users(vec![request("helen@seinfeld.com", "retiree")], false, &store).await;
+    assert!(matches!(batch, Err(RepositoryError::Backend(_))));
+
+    assert!(!exists(&store, "morty@seinfeld.com").await);
+    assert!(!exists(&store, "helen@seinfeld.com").await);
+}